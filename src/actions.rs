@@ -0,0 +1,264 @@
+use crate::ron_asset::RonAssetLoader;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use serde::Deserialize;
+use std::hash::Hash;
+
+const BINDINGS_PATH: &str = "input.bindings.ron";
+
+/// What the player wants to do, interaction systems read these from [`ActionState`] instead of
+/// raw inputs.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Deserialize)]
+pub enum Action {
+    SelectNext,
+    SelectPrevious,
+    /// Pressed to start dragging an item, released to build it.
+    Build,
+    Cancel,
+    Rotate,
+    Discard,
+    Reroll,
+    Sort,
+    ToggleLock,
+    PickUp,
+    Sell,
+    Undo,
+    Redo,
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    /// Held to move the camera with the cursor.
+    DragPan,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum InputKind {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// Any connected gamepad.
+    Gamepad(GamepadButtonType),
+    /// Single finger touch, gestures with more fingers are left to the camera.
+    Touch,
+}
+
+/// Inputs for each action. A binding is a chord: every input held, the last one triggers it.
+#[derive(Asset, TypePath, Resource, Deserialize, Clone)]
+pub struct InputBindings {
+    pub bindings: HashMap<Action, Vec<Vec<InputKind>>>,
+}
+
+/// The bindings file as it was when the game was built, used for actions missing from the
+/// loaded one.
+impl Default for InputBindings {
+    fn default() -> Self {
+        ron::de::from_str(include_str!("../assets/input.bindings.ron"))
+            .expect("the built in bindings file is valid")
+    }
+}
+
+/// Actions triggered this frame, updated right after the raw inputs.
+///
+/// Chords sharing their last input all trigger, systems check the longest one first
+/// (`Sell` before `PickUp`, `Redo` before `Undo`).
+#[derive(Resource, Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    just_released: HashSet<Action>,
+    /// A second finger touched since all fingers were last lifted.
+    multi_touch: bool,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.just_released.contains(&action)
+    }
+
+    /// Later systems won't see this frame press or release of `action`.
+    pub fn consume(&mut self, action: Action) {
+        self.just_pressed.remove(&action);
+        self.just_released.remove(&action);
+    }
+}
+
+/// Updates [`ActionState`] in `PreUpdate`.
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct ActionSet;
+
+/// Systems reacting to actions in `Update`, ordered so item interactions can
+/// [`ActionState::consume`] an action before world interactions see it.
+///
+/// Player commands submitted by both are applied in `Execute`, requests they create are checked
+/// and applied in `Resolve`.
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub enum InteractionSet {
+    Item,
+    World,
+    Execute,
+    Resolve,
+}
+
+#[derive(Resource)]
+struct BindingsHandle(Handle<InputBindings>);
+
+pub struct ActionsPlugin;
+
+impl Plugin for ActionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<InputBindings>();
+        app.register_asset_loader(RonAssetLoader::<InputBindings>::new(&["bindings.ron"]));
+        app.init_resource::<InputBindings>();
+        app.init_resource::<ActionState>();
+        app.configure_sets(
+            Update,
+            (
+                InteractionSet::Item,
+                InteractionSet::World,
+                InteractionSet::Execute,
+                InteractionSet::Resolve,
+            )
+                .chain(),
+        );
+        app.add_systems(Startup, load_bindings);
+        app.add_systems(
+            PreUpdate,
+            (apply_loaded_bindings, update_actions)
+                .chain()
+                .in_set(ActionSet)
+                .after(InputSystem),
+        );
+    }
+}
+
+fn load_bindings(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(BindingsHandle(asset_server.load(BINDINGS_PATH)));
+}
+
+/// Also applies changes of the file while the game runs, when hot reloading is enabled.
+fn apply_loaded_bindings(
+    mut events: EventReader<AssetEvent<InputBindings>>,
+    handle: Option<Res<BindingsHandle>>,
+    assets: Res<Assets<InputBindings>>,
+    mut bindings: ResMut<InputBindings>,
+) {
+    let Some(handle) = handle else {
+        return;
+    };
+    for event in events.read() {
+        if !event.is_loaded_with_dependencies(&handle.0) && !event.is_modified(&handle.0) {
+            continue;
+        }
+        let Some(loaded) = assets.get(&handle.0) else {
+            continue;
+        };
+        let mut merged = InputBindings::default();
+        merged.bindings.extend(loaded.bindings.clone());
+        *bindings = merged;
+        info!("input bindings loaded from {BINDINGS_PATH}");
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Edge {
+    Pressed,
+    JustPressed,
+    JustReleased,
+}
+
+fn check<T: Copy + Eq + Hash + Send + Sync>(input: &Input<T>, value: T, edge: Edge) -> bool {
+    match edge {
+        Edge::Pressed => input.pressed(value),
+        Edge::JustPressed => input.just_pressed(value),
+        Edge::JustReleased => input.just_released(value),
+    }
+}
+
+fn update_actions(
+    bindings: Res<InputBindings>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    touches: Res<Touches>,
+    mut state: ResMut<ActionState>,
+) {
+    let touching = touches.iter().count();
+    if touching > 1 {
+        state.multi_touch = true;
+    }
+    let single_touch = !state.multi_touch;
+    let input = |kind: &InputKind, edge: Edge| match kind {
+        InputKind::Key(key) => check(&keys, *key, edge),
+        InputKind::Mouse(button) => check(&mouse, *button, edge),
+        InputKind::Gamepad(button_type) => gamepads.iter().any(|gamepad| {
+            check(
+                &gamepad_buttons,
+                GamepadButton::new(gamepad, *button_type),
+                edge,
+            )
+        }),
+        InputKind::Touch => {
+            single_touch
+                && match edge {
+                    Edge::Pressed => touching == 1,
+                    Edge::JustPressed => touches.any_just_pressed(),
+                    Edge::JustReleased => touches.any_just_released(),
+                }
+        }
+    };
+    let chord = |chord: &Vec<InputKind>, edge: Edge| {
+        let Some((last, held)) = chord.split_last() else {
+            return false;
+        };
+        held.iter().all(|kind| input(kind, Edge::Pressed)) && input(last, edge)
+    };
+    let state = &mut *state;
+    state.pressed.clear();
+    state.just_pressed.clear();
+    state.just_released.clear();
+    for (action, chords) in bindings.bindings.iter() {
+        for (set, edge) in [
+            (&mut state.pressed, Edge::Pressed),
+            (&mut state.just_pressed, Edge::JustPressed),
+            (&mut state.just_released, Edge::JustReleased),
+        ] {
+            if chords.iter().any(|c| chord(c, edge)) {
+                set.insert(*action);
+            }
+        }
+    }
+    if touching == 0 {
+        state.multi_touch = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_bindings_dont_overlap() {
+        let bindings = InputBindings::default();
+        let triggers = |action: Action| -> Vec<InputKind> {
+            bindings.bindings[&action]
+                .iter()
+                .filter_map(|chord| chord.last().copied())
+                .collect()
+        };
+        let lock = triggers(Action::ToggleLock);
+        assert!(!lock.is_empty());
+        for other in [Action::PickUp, Action::Sell, Action::Build] {
+            assert!(triggers(other).iter().all(|input| !lock.contains(input)));
+        }
+    }
+}
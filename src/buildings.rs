@@ -1,59 +1,59 @@
+pub mod behavior;
+pub mod footprint;
+pub mod history;
 pub mod interaction;
+pub mod pickup;
+pub mod queue;
 
-use super::HIGHLIGHT_TINT;
 use super::ITEM_VISUAL_SIZE;
+use crate::camera::ScreenAnchored;
+use crate::crafting::CraftingPlugin;
+use crate::currency::{BuildCost, Cost, Currency};
 use crate::inventory_generic::*;
+use crate::item_visual::ItemDef;
+use crate::stats::{BaseStats, Stats, StatsPlugin};
+use crate::tooltip::{ItemInfo, ItemMetadata, ItemTooltipPlugin};
 use bevy::ecs::system::EntityCommand;
 use bevy::math::vec3;
 use bevy::prelude::*;
-use bevy::sprite::MaterialMesh2dBundle;
 use bevy::sprite::Mesh2dHandle;
 use bevy::utils::HashMap;
-use bevy_mod_picking::PickableBundle;
+use interaction::NoBuildArea;
+use serde::Deserialize;
+
+const RECIPES_PATH: &str = "buildings.recipes.ron";
 
 pub struct Plugin;
 
 impl bevy::prelude::Plugin for Plugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(crate::inventory_generic::InventoryPlugin::<ItemType>::default());
+        app.add_plugins(ItemTooltipPlugin::<ItemType>::default());
+        app.add_plugins(StatsPlugin::<ItemType>::default());
+        app.add_plugins(behavior::Plugin);
+        app.add_plugins(queue::Plugin);
+        app.add_plugins(pickup::Plugin);
+        app.add_plugins(history::Plugin);
+        app.add_plugins(crate::loot::LootPlugin::<ItemType>::default());
+        app.add_plugins(CraftingPlugin::<ItemType>::new(RECIPES_PATH));
+        // Requests of a tick resolve in the same order on every machine.
+        app.configure_sets(
+            Update,
+            crate::crafting::CraftingSet.after(interaction::BuildSet),
+        );
         app.add_systems(Startup, (create_assets, spawn_layout).chain());
     }
 }
 
-#[derive(Resource)]
-pub struct ItemDef {
-    pub mesh: Mesh2dHandle,
-    pub material: Handle<ColorMaterial>,
-}
-
-impl ItemDef {
-    pub(crate) fn create_item_visual(
-        &self,
-    ) -> (
-        MaterialMesh2dBundle<ColorMaterial>,
-        bevy_mod_picking::prelude::Highlight<ColorMaterial>,
-        PickableBundle,
-    ) {
-        (
-            MaterialMesh2dBundle {
-                mesh: self.mesh.clone(),
-                transform: Transform::default().with_scale(Vec3::splat(ITEM_VISUAL_SIZE)),
-                material: self.material.clone(),
-                ..default()
-            },
-            HIGHLIGHT_TINT,
-            PickableBundle::default(), // <- Makes the mesh pickable.
-        )
-    }
-}
-
 #[derive(Resource)]
 pub struct VisualAssets {
     pub item_def: HashMap<ItemType, ItemDef>,
 }
 
+/// Placeholder shapes, except for the Tesla drawn from its icon.
 pub(crate) fn create_assets(
     mut commands: Commands,
+    asset_server: Res<AssetServer>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
@@ -62,57 +62,80 @@ pub(crate) fn create_assets(
         item_def: [
             (
                 ItemType::Gun,
-                ItemDef {
-                    mesh: quad.clone(),
-                    material: materials.add(ColorMaterial::from(Color::RED)),
-                },
+                ItemDef::from_color(quad.clone(), &mut materials, Color::RED),
             ),
             (
                 ItemType::Rifle,
-                ItemDef {
-                    mesh: quad.clone(),
-                    material: materials.add(ColorMaterial::from(Color::YELLOW)),
-                },
+                ItemDef::from_color(
+                    meshes
+                        .add(Mesh::from(shape::Quad::new(ItemType::Rifle.footprint())))
+                        .into(),
+                    &mut materials,
+                    Color::YELLOW,
+                ),
             ),
             (
                 ItemType::Aura,
-                ItemDef {
-                    mesh: quad.clone(),
-                    material: materials.add(ColorMaterial::from(Color::PURPLE)),
-                },
+                ItemDef::from_color(quad.clone(), &mut materials, Color::PURPLE),
+            ),
+            (
+                ItemType::Tesla,
+                ItemDef::from_image(
+                    quad.clone(),
+                    &mut materials,
+                    asset_server.load("icons/tesla.png"),
+                ),
             ),
         ]
         .into(),
     });
 }
 
-pub(crate) fn spawn_layout(mut commands: Commands) {
+/// How many items [`queue::Upcoming`] shows after the visible slots.
+const UPCOMING_LOOK_AHEAD: usize = 3;
+/// Visible slots plus room for picked up buildings.
+const QUEUE_CAPACITY: usize = 5;
+
+pub(crate) fn spawn_layout(mut commands: Commands, rng: Res<crate::RandomDeterministic>) {
     let inventory = vec![
         commands.spawn(ItemType::Gun).id(),
         commands.spawn(ItemType::Rifle).id(),
         commands.spawn(ItemType::Aura).id(),
     ]
     .into();
-    commands.spawn((
+    let slots = vec![
+        vec3(0f32, 0f32, 0f32),
+        vec3(0f32, ITEM_VISUAL_SIZE + 10f32, 0f32),
+        vec3(0f32, (ITEM_VISUAL_SIZE + 10f32) * 2f32, 0f32),
+    ];
+    let area = NoBuildArea::from_slots(&slots);
+    let mut layout = commands.spawn((
         Inventory::<ItemType> {
             items: inventory,
             ..default()
         },
         InventoryVisualDef {
-            positions: vec![
-                vec3(0f32, 0f32, 0f32),
-                vec3(0f32, ITEM_VISUAL_SIZE + 10f32, 0f32),
-                vec3(0f32, (ITEM_VISUAL_SIZE + 10f32) * 2f32, 0f32),
-            ],
+            positions: slots.clone(),
+        },
+        ScreenAnchored {
+            camera: None,
+            offsets: slots,
         },
+        queue::Upcoming::new(UPCOMING_LOOK_AHEAD, rng.fork("buildings/upcoming")),
+        Capacity(QUEUE_CAPACITY),
     ));
+    if let Some(area) = area {
+        layout.insert(area);
+    }
 }
 
-#[derive(Component, Clone, Copy, Hash, Eq, PartialEq)]
+#[derive(Component, Clone, Copy, Debug, Hash, Eq, PartialEq, TypePath, Deserialize)]
 pub enum ItemType {
     Gun,
     Rifle,
     Aura,
+    /// Only obtained by crafting.
+    Tesla,
 }
 
 impl CommandVisualBuilder for ItemType {
@@ -122,6 +145,78 @@ impl CommandVisualBuilder for ItemType {
     }
 }
 
+impl ItemType {
+    /// Size of the visual and of the space taken once placed, before [`ITEM_VISUAL_SIZE`] and
+    /// rotation are applied.
+    pub fn footprint(&self) -> Vec2 {
+        match self {
+            ItemType::Rifle => Vec2::new(1f32, 0.5f32),
+            _ => Vec2::ONE,
+        }
+    }
+}
+
+impl ItemMetadata for ItemType {
+    fn item_info(&self) -> ItemInfo {
+        let (name, description) = match self {
+            ItemType::Gun => ("Gun", "Shoots the closest enemy."),
+            ItemType::Rifle => ("Rifle", "Slow but long ranged shots."),
+            ItemType::Aura => ("Aura", "Empowers nearby buildings."),
+            ItemType::Tesla => ("Tesla", "Rapid short ranged zaps."),
+        };
+        ItemInfo {
+            name: name.to_string(),
+            description: description.to_string(),
+            stats: vec![("Cost".to_string(), self.build_cost().describe())],
+            ..default()
+        }
+    }
+
+    fn visual_size(&self) -> Vec2 {
+        self.footprint()
+    }
+}
+
+impl BuildCost for ItemType {
+    fn build_cost(&self) -> Cost {
+        Cost(match self {
+            ItemType::Gun => vec![(Currency::Gold, 10)],
+            ItemType::Rifle => vec![(Currency::Gold, 20), (Currency::Energy, 5)],
+            ItemType::Aura => vec![(Currency::Gold, 15), (Currency::Energy, 10)],
+            ItemType::Tesla => vec![(Currency::Gold, 25), (Currency::Energy, 10)],
+        })
+    }
+}
+
+impl BaseStats for ItemType {
+    fn base_stats(&self) -> Stats {
+        match self {
+            ItemType::Gun => Stats {
+                damage: 10f32,
+                range: 150f32,
+                fire_rate: 2f32,
+                ..default()
+            },
+            ItemType::Rifle => Stats {
+                damage: 30f32,
+                range: 300f32,
+                fire_rate: 0.5f32,
+                ..default()
+            },
+            ItemType::Aura => Stats {
+                radius: 120f32,
+                ..default()
+            },
+            ItemType::Tesla => Stats {
+                damage: 6f32,
+                range: 110f32,
+                fire_rate: 6f32,
+                ..default()
+            },
+        }
+    }
+}
+
 pub struct CreateItemDefVisual {
     pub item_type: ItemType,
 }
@@ -0,0 +1,217 @@
+use super::ItemType;
+use crate::health::{DamageEvent, Health, HealthSet};
+use crate::inventory_generic::MarkerPlaced;
+use crate::player_command::{Simulation, SimulationSet};
+use crate::stats::{FinalStats, Level};
+use crate::ITEM_VISUAL_SIZE;
+use bevy::prelude::*;
+use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+
+/// Fire rate bonus given by each aura a turret stands in.
+const AURA_FIRE_RATE_BONUS: f32 = 0.25f32;
+const PROJECTILE_SPEED: f32 = 600f32;
+const PROJECTILE_SIZE: f32 = 10f32;
+const BUILDING_HEALTH: f32 = 100f32;
+/// Size increase of the building visual for each level above the first.
+const LEVEL_SCALE_STEP: f32 = 0.15f32;
+
+pub struct Plugin;
+
+impl bevy::prelude::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, create_projectile_assets);
+        app.add_systems(
+            Update,
+            (
+                attach_behaviors,
+                apply_deferred,
+                apply_auras,
+                fire_turrets,
+                move_projectiles,
+            )
+                .chain()
+                .before(HealthSet::Damage)
+                .in_set(SimulationSet),
+        );
+        app.add_systems(Update, (draw_auras, show_levels));
+    }
+}
+
+/// Shoots at the closest target in range, added to `Gun`, `Rifle` and `Tesla` once placed.
+#[derive(Component)]
+pub struct Turret {
+    /// Seconds before next shot.
+    pub cooldown: f32,
+    /// Multiplies the fire rate, driven by nearby auras.
+    pub fire_rate_multiplier: f32,
+}
+
+impl Default for Turret {
+    fn default() -> Self {
+        Self {
+            cooldown: 0f32,
+            fire_rate_multiplier: 1f32,
+        }
+    }
+}
+
+/// Buffs turrets within its radius, added to `Aura` once placed.
+#[derive(Component)]
+pub struct AuraEmitter;
+
+/// Something turrets can shoot at.
+#[derive(Component)]
+pub struct MarkerTarget;
+
+#[derive(Component)]
+pub struct Projectile {
+    pub target: Entity,
+    pub damage: f32,
+}
+
+#[derive(Resource)]
+struct ProjectileAssets {
+    mesh: Mesh2dHandle,
+    material: Handle<ColorMaterial>,
+}
+
+fn create_projectile_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.insert_resource(ProjectileAssets {
+        mesh: meshes.add(Mesh::from(shape::Circle::default())).into(),
+        // Above 1.0 to glow with the camera bloom.
+        material: materials.add(ColorMaterial::from(Color::rgb(3.0, 3.0, 1.5))),
+    });
+}
+
+fn attach_behaviors(
+    mut commands: Commands,
+    q_placed: Query<(Entity, &ItemType), Added<MarkerPlaced>>,
+) {
+    for (entity, item_type) in q_placed.iter() {
+        commands.entity(entity).insert(Health::new(BUILDING_HEALTH));
+        match item_type {
+            ItemType::Gun | ItemType::Rifle | ItemType::Tesla => {
+                commands.entity(entity).insert(Turret::default());
+            }
+            ItemType::Aura => {
+                commands.entity(entity).insert(AuraEmitter);
+            }
+        }
+    }
+}
+
+fn apply_auras(
+    q_auras: Query<(&Transform, &FinalStats), With<AuraEmitter>>,
+    mut q_turrets: Query<(&Transform, &mut Turret)>,
+) {
+    for (transform, mut turret) in q_turrets.iter_mut() {
+        let auras = q_auras
+            .iter()
+            .filter(|(aura, stats)| {
+                aura.translation
+                    .truncate()
+                    .distance(transform.translation.truncate())
+                    <= stats.0.radius
+            })
+            .count();
+        turret.fire_rate_multiplier = 1f32 + auras as f32 * AURA_FIRE_RATE_BONUS;
+    }
+}
+
+fn fire_turrets(
+    mut commands: Commands,
+    time: Res<Time<Simulation>>,
+    assets: Res<ProjectileAssets>,
+    mut q_turrets: Query<(&Transform, &FinalStats, &mut Turret)>,
+    q_targets: Query<(Entity, &Transform), With<MarkerTarget>>,
+) {
+    for (transform, stats, mut turret) in q_turrets.iter_mut() {
+        turret.cooldown -= time.delta_seconds();
+        if turret.cooldown > 0f32 {
+            continue;
+        }
+        let position = transform.translation.truncate();
+        let Some((target, _)) = q_targets
+            .iter()
+            .map(|(e, t)| (e, t.translation.truncate().distance(position)))
+            .filter(|(_, distance)| *distance <= stats.0.range)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+        else {
+            // Stay ready to shoot as soon as something comes in range.
+            turret.cooldown = 0f32;
+            continue;
+        };
+        turret.cooldown = 1f32 / (stats.0.fire_rate * turret.fire_rate_multiplier);
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: assets.mesh.clone(),
+                material: assets.material.clone(),
+                transform: Transform::from_translation(position.extend(1f32))
+                    .with_scale(Vec3::splat(PROJECTILE_SIZE)),
+                ..default()
+            },
+            Projectile {
+                target,
+                damage: stats.0.damage,
+            },
+        ));
+    }
+}
+
+fn move_projectiles(
+    mut commands: Commands,
+    time: Res<Time<Simulation>>,
+    mut damages: EventWriter<DamageEvent>,
+    mut q_projectiles: Query<(Entity, &Projectile, &mut Transform), Without<MarkerTarget>>,
+    q_targets: Query<&Transform, With<MarkerTarget>>,
+) {
+    for (entity, projectile, mut transform) in q_projectiles.iter_mut() {
+        let Ok(target) = q_targets.get(projectile.target) else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+        let to_target = target.translation.truncate() - transform.translation.truncate();
+        let step = PROJECTILE_SPEED * time.delta_seconds();
+        if to_target.length() <= step {
+            damages.send(DamageEvent {
+                target: projectile.target,
+                amount: projectile.damage,
+            });
+            commands.entity(entity).despawn();
+            continue;
+        }
+        transform.translation += (to_target.normalize() * step).extend(0f32);
+    }
+}
+
+fn draw_auras(mut gizmos: Gizmos, q_auras: Query<(&Transform, &FinalStats), With<AuraEmitter>>) {
+    for (transform, stats) in q_auras.iter() {
+        gizmos.circle_2d(
+            transform.translation.truncate(),
+            stats.0.radius,
+            Color::PURPLE,
+        );
+    }
+}
+
+/// Upgraded buildings are drawn bigger, back in an inventory they take a normal slot.
+#[allow(clippy::type_complexity)]
+fn show_levels(
+    mut q_levels: Query<
+        (&Level, &mut Transform),
+        (
+            With<ItemType>,
+            With<MarkerPlaced>,
+            Or<(Changed<Level>, Added<MarkerPlaced>)>,
+        ),
+    >,
+) {
+    for (level, mut transform) in q_levels.iter_mut() {
+        let scale = 1f32 + LEVEL_SCALE_STEP * level.0.saturating_sub(1) as f32;
+        transform.scale = Vec3::splat(ITEM_VISUAL_SIZE * scale);
+    }
+}
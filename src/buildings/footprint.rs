@@ -0,0 +1,139 @@
+use bevy::math::Affine3A;
+use bevy::prelude::*;
+
+/// Corners of a rectangle of `size` centered on the origin, placed with `affine` (its scale and
+/// rotation included), in world coordinates.
+pub fn corners(affine: Affine3A, size: Vec2) -> [Vec2; 4] {
+    let half = size / 2f32;
+    [
+        Vec2::new(-half.x, -half.y),
+        Vec2::new(-half.x, half.y),
+        Vec2::new(half.x, half.y),
+        Vec2::new(half.x, -half.y),
+    ]
+    .map(|corner| affine.transform_point3(corner.extend(0f32)).truncate())
+}
+
+/// Whether two placed rectangles overlap, touching edges don't count.
+pub fn overlap(a: &[Vec2; 4], b: &[Vec2; 4]) -> bool {
+    // Separating axis theorem: rectangles are apart if their projections on one of their
+    // edge normals are.
+    let axes = [a[1] - a[0], a[3] - a[0], b[1] - b[0], b[3] - b[0]];
+    axes.iter().all(|axis| {
+        let project = |corners: &[Vec2; 4]| {
+            corners
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(min, max), corner| {
+                    let p = corner.dot(*axis);
+                    (min.min(p), max.max(p))
+                })
+        };
+        let (a_min, a_max) = project(a);
+        let (b_min, b_max) = project(b);
+        // Tolerance for rounding of rotated corners.
+        let epsilon = 1e-3 * axis.length_squared();
+        a_min < b_max - epsilon && b_min < a_max - epsilon
+    })
+}
+
+/// Rectangle covering the slots of an inventory, `positions` being their centers and
+/// `slot_size` their side, `None` without slots.
+pub fn slots_area(positions: &[Vec3], slot_size: f32) -> Option<[Vec2; 4]> {
+    let first = positions.first()?.truncate();
+    let (min, max) = positions.iter().fold((first, first), |(min, max), p| {
+        (min.min(p.truncate()), max.max(p.truncate()))
+    });
+    let half = Vec2::splat(slot_size / 2f32);
+    let rect = Rect::from_corners(min - half, max + half);
+    Some(corners(
+        Affine3A::from_translation(rect.center().extend(0f32)),
+        rect.size(),
+    ))
+}
+
+/// Whether a building with `footprint` corners can't be placed, because it overlaps one of the
+/// `obstacles`: placed buildings or inventory areas.
+pub fn blocked(footprint: &[Vec2; 4], mut obstacles: impl Iterator<Item = [Vec2; 4]>) -> bool {
+    obstacles.any(|other| overlap(footprint, &other))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    fn rect(center: Vec2, size: Vec2, angle: f32) -> [Vec2; 4] {
+        let affine =
+            Affine3A::from_rotation_translation(Quat::from_rotation_z(angle), center.extend(0f32));
+        corners(affine, size)
+    }
+
+    #[test]
+    fn overlapping_rectangles() {
+        let a = rect(Vec2::ZERO, Vec2::splat(64f32), 0f32);
+        let b = rect(Vec2::new(32f32, 10f32), Vec2::splat(64f32), 0f32);
+        assert!(overlap(&a, &b));
+        assert!(overlap(&b, &a));
+    }
+
+    #[test]
+    fn touching_rectangles_dont_overlap() {
+        let a = rect(Vec2::ZERO, Vec2::splat(64f32), 0f32);
+        let b = rect(Vec2::new(64f32, 0f32), Vec2::splat(64f32), 0f32);
+        assert!(!overlap(&a, &b));
+        // Rotated by a quarter turn, corners are rounded but still touch.
+        let c = rect(Vec2::new(0f32, 64f32), Vec2::splat(64f32), FRAC_PI_2);
+        assert!(!overlap(&a, &c));
+    }
+
+    #[test]
+    fn rotated_rectangles() {
+        let a = rect(Vec2::ZERO, Vec2::splat(64f32), 0f32);
+        // A diamond whose corner enters the square, bounding boxes alone can't tell.
+        let diamond = rect(Vec2::new(70f32, 0f32), Vec2::splat(64f32), FRAC_PI_4);
+        assert!(overlap(&a, &diamond));
+        // Its corner reaches 70 - 45.25, nearly touching the square's corner.
+        let diamond = rect(Vec2::new(60f32, 60f32), Vec2::splat(64f32), FRAC_PI_4);
+        assert!(!overlap(&a, &diamond));
+    }
+
+    #[test]
+    fn rotated_long_footprint() {
+        // A rifle-like footprint, twice as long as wide, turned upright.
+        let rifle = rect(Vec2::ZERO, Vec2::new(128f32, 64f32), FRAC_PI_2);
+        let right = rect(Vec2::new(80f32, 0f32), Vec2::splat(64f32), 0f32);
+        assert!(!overlap(&rifle, &right));
+        let above = rect(Vec2::new(0f32, 80f32), Vec2::splat(64f32), 0f32);
+        assert!(overlap(&rifle, &above));
+    }
+
+    #[test]
+    fn blocked_by_any_obstacle() {
+        let footprint = rect(Vec2::ZERO, Vec2::splat(64f32), 0f32);
+        let far = rect(Vec2::new(200f32, 0f32), Vec2::splat(64f32), 0f32);
+        let near = rect(Vec2::new(50f32, 50f32), Vec2::splat(64f32), 0f32);
+        assert!(!blocked(&footprint, [far].into_iter()));
+        assert!(blocked(&footprint, [far, near].into_iter()));
+        assert!(!blocked(&footprint, std::iter::empty()));
+    }
+
+    #[test]
+    fn slots_area_covers_every_slot() {
+        assert!(slots_area(&[], 64f32).is_none());
+        let area = slots_area(
+            &[
+                Vec3::new(100f32, 0f32, 0f32),
+                Vec3::new(100f32, 74f32, 0f32),
+                Vec3::new(100f32, 148f32, 0f32),
+            ],
+            64f32,
+        )
+        .unwrap();
+        let inside = rect(Vec2::new(100f32, 100f32), Vec2::splat(10f32), 0f32);
+        let beside = rect(Vec2::new(164f32, 100f32), Vec2::splat(64f32), 0f32);
+        let below = rect(Vec2::new(100f32, -64f32), Vec2::splat(64f32), 0f32);
+        assert!(overlap(&area, &inside));
+        assert!(!overlap(&area, &beside));
+        assert!(!overlap(&area, &below));
+    }
+}
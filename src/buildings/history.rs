@@ -0,0 +1,685 @@
+use super::behavior::{AuraEmitter, Turret};
+use super::interaction::Origin;
+use super::queue::{Locked, MarkerUpcoming, Upcoming};
+use super::ItemType;
+use crate::actions::{Action, ActionState, InteractionSet};
+use crate::currency::{Currency, Wallet};
+use crate::health::{Died, Health, HealthSet};
+use crate::inventory_generic::{CommandVisualBuilder, Inventory, MarkerItemVisual, MarkerPlaced};
+use crate::loot::Looted;
+use crate::player_command::{
+    execute_commands, CommandKind, CommandQueue, LocalPlayer, PlayerId, SimulationSet, Tick,
+};
+use crate::rarity::Rarity;
+use crate::stats::{Level, Modifiers};
+use crate::ITEM_VISUAL_SIZE;
+use bevy::ecs::system::{EntityCommand, SystemState};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use rand_chacha::ChaCha20Rng;
+
+/// Oldest states are dropped past this many undo steps.
+const MAX_HISTORY: usize = 100;
+
+/// [`Action::Undo`] reverts the last player change of the building inventories or placed
+/// buildings, [`Action::Redo`] applies it again. Players can only undo or redo their own
+/// changes: when the last one was made by another player, the request is refused.
+///
+/// The whole state is recorded before a tick with player commands: items order of each
+/// inventory, the upcoming items with their random stream, and placed buildings positions and
+/// rotations. It becomes an undo step if the commands changed it. Money spent or earned by the
+/// change is given back, items despawned since are spawned again, except buildings destroyed
+/// since. Looted items which arrived since stay.
+pub struct Plugin;
+
+impl bevy::prelude::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<History>();
+        app.add_event::<HistoryRequest>();
+        app.add_systems(Update, submit_undo_redo.in_set(InteractionSet::World));
+        app.add_systems(
+            Update,
+            snapshot_before_commands
+                .in_set(InteractionSet::Execute)
+                .before(execute_commands),
+        );
+        // Last of the requests, so it reverts the whole tick before it.
+        app.add_systems(
+            Update,
+            undo_redo
+                .in_set(InteractionSet::Resolve)
+                .after(super::pickup::pick_up_or_sell),
+        );
+        // Once requests are applied, before the simulation changes the world.
+        app.add_systems(
+            Update,
+            (apply_deferred, record_changes)
+                .chain()
+                .after(InteractionSet::Resolve)
+                .before(SimulationSet),
+        );
+        app.add_systems(Update, track_destroyed.in_set(HealthSet::Death));
+    }
+}
+
+/// Sent by [`crate::player_command`] for [`CommandKind::Undo`] and [`CommandKind::Redo`] of a
+/// player.
+#[derive(Event, Clone, Copy, PartialEq)]
+pub enum HistoryRequest {
+    Undo(PlayerId),
+    Redo(PlayerId),
+}
+
+#[derive(Clone, PartialEq)]
+struct ItemData {
+    item_type: ItemType,
+    rarity: Option<Rarity>,
+    modifiers: Option<Modifiers>,
+    level: Option<Level>,
+}
+
+#[derive(Clone, PartialEq)]
+struct InventoryState {
+    inventory: Entity,
+    items: Vec<Entity>,
+    upcoming: Vec<Entity>,
+    rng: ChaCha20Rng,
+}
+
+#[derive(Clone)]
+struct Snapshot {
+    inventories: Vec<InventoryState>,
+    /// (building, translation, rotation, origin inventory), sorted by entity.
+    placed: Vec<(Entity, Vec3, Quat, Entity)>,
+    items: HashMap<Entity, ItemData>,
+    /// Not compared, income alone isn't a change worth undoing.
+    wallet: HashMap<Currency, u32>,
+}
+
+impl Snapshot {
+    fn same_layout(&self, other: &Snapshot) -> bool {
+        self.inventories == other.inventories
+            && self.placed == other.placed
+            && self.items == other.items
+    }
+
+    /// Forgets `entities`, wherever they are.
+    fn remove(&mut self, entities: &HashSet<Entity>) {
+        for state in self.inventories.iter_mut() {
+            state.items.retain(|e| !entities.contains(e));
+            state.upcoming.retain(|e| !entities.contains(e));
+        }
+        self.placed
+            .retain(|(building, _, _, _)| !entities.contains(building));
+        self.items.retain(|e, _| !entities.contains(e));
+    }
+
+    fn map_entities(&mut self, map: impl Fn(Entity) -> Entity) {
+        for state in self.inventories.iter_mut() {
+            state.items.iter_mut().for_each(|e| *e = map(*e));
+            state.upcoming.iter_mut().for_each(|e| *e = map(*e));
+        }
+        for (building, _, _, _) in self.placed.iter_mut() {
+            *building = map(*building);
+        }
+        self.items = self.items.drain().map(|(e, data)| (map(e), data)).collect();
+    }
+}
+
+/// State to go back to, with what the change spent (or earned, when negative).
+struct Step {
+    snapshot: Snapshot,
+    spent: HashMap<Currency, i64>,
+    /// Whose commands made the change, sorted.
+    players: Vec<PlayerId>,
+}
+
+impl Step {
+    fn between(before: Snapshot, after: &Snapshot, players: Vec<PlayerId>) -> Self {
+        let spent = Currency::ALL
+            .iter()
+            .map(|currency| {
+                let amount = |wallet: &HashMap<Currency, u32>| {
+                    wallet.get(currency).copied().unwrap_or_default() as i64
+                };
+                (*currency, amount(&before.wallet) - amount(&after.wallet))
+            })
+            .collect();
+        Self {
+            snapshot: before,
+            spent,
+            players,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+struct History {
+    /// State before the commands of this tick, until they are applied, with the players who
+    /// sent them.
+    pending: Option<(Snapshot, Vec<PlayerId>)>,
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    /// Items despawned then spawned again by an undo, old entity to new one.
+    respawned: HashMap<Entity, Entity>,
+    /// Buildings which died, they aren't spawned again.
+    destroyed: HashSet<Entity>,
+}
+
+impl History {
+    fn resolve(&self, mut entity: Entity) -> Entity {
+        while let Some(respawned) = self.respawned.get(&entity) {
+            entity = *respawned;
+        }
+        entity
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn take_snapshot(
+    q_inventory: &Query<(Entity, &Inventory<ItemType>, &Upcoming)>,
+    q_placed: &Query<(Entity, &Transform, &Origin), (With<ItemType>, With<MarkerPlaced>)>,
+    q_data: &Query<(
+        &ItemType,
+        Option<&Rarity>,
+        Option<&Modifiers>,
+        Option<&Level>,
+    )>,
+    wallet: &Wallet,
+) -> Snapshot {
+    let inventories: Vec<InventoryState> = q_inventory
+        .iter()
+        .map(|(inventory, items, upcoming)| InventoryState {
+            inventory,
+            items: items.items.iter().copied().collect(),
+            upcoming: upcoming.items.iter().copied().collect(),
+            rng: upcoming.rng.clone(),
+        })
+        .collect();
+    let mut placed: Vec<(Entity, Vec3, Quat, Entity)> = q_placed
+        .iter()
+        .map(|(building, transform, origin)| {
+            (
+                building,
+                transform.translation,
+                transform.rotation,
+                origin.inventory,
+            )
+        })
+        .collect();
+    placed.sort_by_key(|(building, _, _, _)| *building);
+    let tracked = inventories
+        .iter()
+        .flat_map(|state| state.items.iter().chain(state.upcoming.iter()))
+        .chain(placed.iter().map(|(building, _, _, _)| building));
+    let items = tracked
+        .filter_map(|entity| {
+            let (item_type, rarity, modifiers, level) = q_data.get(*entity).ok()?;
+            Some((
+                *entity,
+                ItemData {
+                    item_type: *item_type,
+                    rarity: rarity.copied(),
+                    modifiers: modifiers.cloned(),
+                    level: level.copied(),
+                },
+            ))
+        })
+        .collect();
+    Snapshot {
+        inventories,
+        placed,
+        items,
+        wallet: wallet.amounts.clone(),
+    }
+}
+
+type SnapshotQueries<'w, 's> = (
+    Query<'w, 's, (Entity, &'static Inventory<ItemType>, &'static Upcoming)>,
+    Query<
+        'w,
+        's,
+        (Entity, &'static Transform, &'static Origin),
+        (With<ItemType>, With<MarkerPlaced>),
+    >,
+    Query<
+        'w,
+        's,
+        (
+            &'static ItemType,
+            Option<&'static Rarity>,
+            Option<&'static Modifiers>,
+            Option<&'static Level>,
+        ),
+    >,
+    Res<'w, Wallet>,
+);
+
+fn snapshot_world(world: &mut World) -> Snapshot {
+    let mut state = SystemState::<SnapshotQueries>::new(world);
+    let (q_inventory, q_placed, q_data, wallet) = state.get(world);
+    take_snapshot(&q_inventory, &q_placed, &q_data, &wallet)
+}
+
+/// Selections alone don't change anything worth undoing.
+fn snapshot_before_commands(
+    mut history: ResMut<History>,
+    tick: Res<Tick>,
+    queue: Res<CommandQueue>,
+    (q_inventory, q_placed, q_data, wallet): SnapshotQueries,
+) {
+    let mut players: Vec<PlayerId> = queue
+        .pending
+        .iter()
+        .filter(|c| c.tick <= tick.0 && !matches!(c.kind, CommandKind::Select { .. }))
+        .map(|c| c.player)
+        .collect();
+    if players.is_empty() {
+        return;
+    }
+    players.sort();
+    players.dedup();
+    let snapshot = take_snapshot(&q_inventory, &q_placed, &q_data, &wallet);
+    history.pending = Some((snapshot, players));
+}
+
+fn record_changes(
+    mut history: ResMut<History>,
+    (q_inventory, q_placed, q_data, wallet): SnapshotQueries,
+) {
+    let Some((before, players)) = history.pending.take() else {
+        return;
+    };
+    let after = take_snapshot(&q_inventory, &q_placed, &q_data, &wallet);
+    if before.same_layout(&after) {
+        return;
+    }
+    history.undo.push(Step::between(before, &after, players));
+    if history.undo.len() > MAX_HISTORY {
+        history.undo.remove(0);
+    }
+    history.redo.clear();
+}
+
+fn track_destroyed(
+    mut history: ResMut<History>,
+    mut died: EventReader<Died>,
+    q_placed: Query<(), (With<ItemType>, With<MarkerPlaced>)>,
+) {
+    for death in died.read() {
+        if q_placed.contains(death.entity) {
+            history.destroyed.insert(death.entity);
+        }
+    }
+}
+
+fn submit_undo_redo(
+    actions: Res<ActionState>,
+    selection: Query<&PlayerId, With<LocalPlayer>>,
+    tick: Res<Tick>,
+    mut queue: ResMut<CommandQueue>,
+) {
+    let Ok(player) = selection.get_single() else {
+        return;
+    };
+    // Redo chords can contain the undo ones.
+    if actions.just_pressed(Action::Redo) {
+        queue.submit(*player, &tick, CommandKind::Redo);
+    } else if actions.just_pressed(Action::Undo) {
+        queue.submit(*player, &tick, CommandKind::Undo);
+    }
+}
+
+fn undo_redo(world: &mut World) {
+    let requests: Vec<HistoryRequest> = world
+        .resource_mut::<Events<HistoryRequest>>()
+        .drain()
+        .collect();
+    for request in requests {
+        match request {
+            HistoryRequest::Undo(player) => undo_or_redo(world, true, player),
+            HistoryRequest::Redo(player) => undo_or_redo(world, false, player),
+        }
+    }
+}
+
+fn undo_or_redo(world: &mut World, undo: bool, player: PlayerId) {
+    let current = snapshot_world(world);
+    world.resource_scope(|world, mut history: Mut<History>| {
+        // Commands of the same tick are undone with the rest.
+        history.pending = None;
+        let steps = if undo {
+            &mut history.undo
+        } else {
+            &mut history.redo
+        };
+        let Some(last) = steps.last() else {
+            return;
+        };
+        if last.players != [player] {
+            info!("{player:?} cannot revert a change of {:?}", last.players);
+            return;
+        }
+        let Some(target) = steps.pop() else {
+            return;
+        };
+        // Gives back what the undone change spent or earned, income since is kept.
+        let mut wallet = world.resource_mut::<Wallet>();
+        for (currency, spent) in target.spent.iter() {
+            let amount = wallet.amounts.entry(*currency).or_default();
+            *amount = (*amount as i64 + spent).max(0) as u32;
+        }
+        let reverse = Step {
+            spent: target.spent.iter().map(|(c, spent)| (*c, -spent)).collect(),
+            snapshot: current,
+            players: target.players,
+        };
+        restore(world, &mut history, target.snapshot);
+        if undo {
+            history.redo.push(reverse);
+        } else {
+            history.undo.push(reverse);
+        }
+    });
+}
+
+/// Puts the world back in the `target` state.
+fn restore(world: &mut World, history: &mut History, mut target: Snapshot) {
+    target.map_entities(|e| history.resolve(e));
+    target.remove(&history.destroyed);
+
+    // Looted items which arrived since are kept, after the restored ones.
+    let mut q_tracked = world.query::<(Entity, &Inventory<ItemType>, &Upcoming)>();
+    let mut looted: Vec<(Entity, Entity)> = Vec::new();
+    let mut tracked: HashSet<Entity> = HashSet::new();
+    for (inventory, items, upcoming) in q_tracked.iter(world) {
+        for item in items.items.iter().chain(upcoming.items.iter()) {
+            if target.items.contains_key(item) {
+                continue;
+            }
+            if world.get::<Looted>(*item).is_some() {
+                looted.push((inventory, *item));
+            } else {
+                tracked.insert(*item);
+            }
+        }
+    }
+    // Items which appeared since, like refills or crafted items, are removed.
+    let mut q_placed = world.query_filtered::<Entity, (With<ItemType>, With<MarkerPlaced>)>();
+    tracked.extend(q_placed.iter(world));
+    for entity in tracked {
+        if !target.items.contains_key(&entity) {
+            world.entity_mut(entity).despawn_recursive();
+        }
+    }
+
+    // Items which were despawned since are spawned again.
+    let mut respawned = HashMap::new();
+    for (entity, data) in target.items.iter() {
+        if world.get::<ItemType>(*entity).is_none() {
+            let new = world.spawn(data.item_type).id();
+            history.respawned.insert(*entity, new);
+            respawned.insert(*entity, new);
+        }
+    }
+    target.map_entities(|e| respawned.get(&e).copied().unwrap_or(e));
+
+    let upcoming: HashSet<Entity> = target
+        .inventories
+        .iter()
+        .flat_map(|state| state.upcoming.iter().copied())
+        .collect();
+    let placed: HashMap<Entity, (Vec3, Quat, Entity)> = target
+        .placed
+        .iter()
+        .map(|(building, translation, rotation, origin)| {
+            (*building, (*translation, *rotation, *origin))
+        })
+        .collect();
+    for (entity, data) in target.items.iter() {
+        let entity = *entity;
+        restore_item_data(world, entity, data);
+        let is_upcoming = upcoming.contains(&entity);
+        let placement = placed.get(&entity);
+        // Upcoming and placed items always have a visual, inventories create theirs.
+        if (is_upcoming || placement.is_some()) && world.get::<MarkerItemVisual>(entity).is_none() {
+            data.item_type
+                .command_to_create_visual()
+                .apply(entity, world);
+            world.entity_mut(entity).insert(MarkerItemVisual);
+        }
+        let mut item = world.entity_mut(entity);
+        if is_upcoming || placement.is_some() {
+            item.remove::<Locked>();
+        }
+        if is_upcoming {
+            item.insert((MarkerUpcoming, Visibility::Inherited));
+        } else {
+            item.remove::<MarkerUpcoming>();
+        }
+        match placement {
+            Some((translation, rotation, origin)) => {
+                // Already placed buildings keep their health and behaviors.
+                if !item.contains::<MarkerPlaced>() {
+                    item.insert(MarkerPlaced);
+                }
+                item.insert((Origin { inventory: *origin }, Visibility::Inherited));
+                if let Some(mut transform) = item.get_mut::<Transform>() {
+                    transform.translation = *translation;
+                    transform.rotation = *rotation;
+                }
+            }
+            None => {
+                item.remove::<(MarkerPlaced, Origin, Health, Turret, AuraEmitter)>();
+                if let Some(mut transform) = item.get_mut::<Transform>() {
+                    transform.rotation = Quat::IDENTITY;
+                }
+            }
+        }
+        // Levels scale placed buildings, upcoming ones are scaled by their inventory.
+        if !is_upcoming && (placement.is_none() || data.level.is_none()) {
+            if let Some(mut transform) = item.get_mut::<Transform>() {
+                transform.scale = Vec3::splat(ITEM_VISUAL_SIZE);
+            }
+        }
+    }
+
+    for state in target.inventories.iter() {
+        let Some(mut inventory) = world.get_entity_mut(state.inventory) else {
+            continue;
+        };
+        if let Some(mut items) = inventory.get_mut::<Inventory<ItemType>>() {
+            items.items = state.items.iter().copied().collect();
+        }
+        if let Some(mut upcoming) = inventory.get_mut::<Upcoming>() {
+            upcoming.items = state.upcoming.iter().copied().collect();
+            upcoming.rng = state.rng.clone();
+        }
+    }
+    for (inventory, item) in looted {
+        if let Some(mut items) = world.get_mut::<Inventory<ItemType>>(inventory) {
+            items.items.push_back(item);
+        }
+    }
+}
+
+fn restore_item_data(world: &mut World, entity: Entity, data: &ItemData) {
+    let mut item = world.entity_mut(entity);
+    item.insert(data.item_type);
+    match data.rarity {
+        Some(rarity) => {
+            item.insert(rarity);
+        }
+        None => {
+            item.remove::<Rarity>();
+        }
+    }
+    match &data.modifiers {
+        Some(modifiers) if item.get::<Modifiers>() != Some(modifiers) => {
+            item.insert(modifiers.clone());
+        }
+        Some(_) => {}
+        None => {
+            item.remove::<Modifiers>();
+        }
+    }
+    match data.level {
+        Some(level) if item.get::<Level>() != Some(&level) => {
+            item.insert(level);
+        }
+        Some(_) => {}
+        None => {
+            item.remove::<Level>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buildings::pickup::PickUpRequest;
+    use crate::crafting::CraftRequest;
+    use crate::player_command::Simulation;
+    use crate::Selection;
+    use rand::SeedableRng;
+
+    fn history_app() -> (App, Entity) {
+        let mut app = App::new();
+        app.init_resource::<Tick>();
+        app.init_resource::<CommandQueue>();
+        app.init_resource::<Wallet>();
+        app.init_resource::<History>();
+        app.init_resource::<Time<Virtual>>();
+        app.init_resource::<Time<Simulation>>();
+        app.add_event::<CraftRequest>();
+        app.add_event::<PickUpRequest>();
+        app.add_event::<HistoryRequest>();
+        app.add_event::<Died>();
+        app.add_systems(
+            Update,
+            (
+                snapshot_before_commands,
+                execute_commands,
+                apply_deferred,
+                undo_redo,
+                apply_deferred,
+                record_changes,
+            )
+                .chain(),
+        );
+        let items = [ItemType::Gun, ItemType::Aura].map(|item| app.world.spawn(item).id());
+        let inventory = app
+            .world
+            .spawn((
+                Inventory::<ItemType> {
+                    items: items.into(),
+                    ..default()
+                },
+                Upcoming::new(0, ChaCha20Rng::seed_from_u64(0)),
+            ))
+            .id();
+        app.world.spawn((
+            PlayerId(0),
+            Selection {
+                inventories: vec![inventory],
+                selected_index: 0,
+            },
+        ));
+        (app, inventory)
+    }
+
+    fn submit(app: &mut App, kind: CommandKind) {
+        submit_as(app, PlayerId(0), kind);
+    }
+
+    fn submit_as(app: &mut App, player: PlayerId, kind: CommandKind) {
+        let tick = *app.world.resource::<Tick>();
+        app.world
+            .resource_mut::<CommandQueue>()
+            .submit(player, &tick, kind);
+        app.update();
+    }
+
+    fn item_types(app: &App, inventory: Entity) -> Vec<ItemType> {
+        app.world
+            .get::<Inventory<ItemType>>(inventory)
+            .unwrap()
+            .items
+            .iter()
+            .map(|item| *app.world.get::<ItemType>(*item).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn only_commands_are_undone() {
+        let (mut app, inventory) = history_app();
+        app.update();
+        assert_eq!(app.world.resource::<History>().undo.len(), 0);
+
+        submit(
+            &mut app,
+            CommandKind::Discard {
+                inventory: 0,
+                item: 0,
+            },
+        );
+        assert_eq!(app.world.resource::<History>().undo.len(), 1);
+        let after_discard = item_types(&app, inventory);
+        assert_eq!(after_discard[0], ItemType::Aura);
+
+        // Loot arriving isn't a step, and stays when the discard is undone.
+        let looted = app.world.spawn((ItemType::Rifle, Looted)).id();
+        app.world
+            .get_mut::<Inventory<ItemType>>(inventory)
+            .unwrap()
+            .items
+            .push_back(looted);
+        app.update();
+        assert_eq!(app.world.resource::<History>().undo.len(), 1);
+
+        submit(&mut app, CommandKind::Undo);
+        assert_eq!(
+            item_types(&app, inventory),
+            vec![ItemType::Gun, ItemType::Aura, ItemType::Rifle]
+        );
+        let history = app.world.resource::<History>();
+        assert_eq!((history.undo.len(), history.redo.len()), (0, 1));
+
+        submit(&mut app, CommandKind::Redo);
+        assert_eq!(item_types(&app, inventory)[..2], after_discard[..]);
+        assert_eq!(app.world.resource::<History>().undo.len(), 1);
+    }
+
+    #[test]
+    fn players_only_revert_their_own_changes() {
+        let (mut app, inventory) = history_app();
+        app.world.spawn((
+            PlayerId(1),
+            Selection {
+                inventories: vec![inventory],
+                selected_index: 0,
+            },
+        ));
+        submit_as(
+            &mut app,
+            PlayerId(0),
+            CommandKind::Discard {
+                inventory: 0,
+                item: 0,
+            },
+        );
+        let after_discard = item_types(&app, inventory);
+
+        submit_as(&mut app, PlayerId(1), CommandKind::Undo);
+        assert_eq!(item_types(&app, inventory), after_discard);
+        assert_eq!(app.world.resource::<History>().undo.len(), 1);
+
+        submit_as(&mut app, PlayerId(0), CommandKind::Undo);
+        assert_eq!(
+            item_types(&app, inventory),
+            vec![ItemType::Gun, ItemType::Aura]
+        );
+        submit_as(&mut app, PlayerId(1), CommandKind::Redo);
+        assert_eq!(app.world.resource::<History>().redo.len(), 1);
+    }
+}
@@ -1,21 +1,47 @@
-use crate::simple_mouse::MouseWorldPosition;
+use super::footprint;
+use crate::actions::{Action, ActionState, InteractionSet};
+use crate::simple_mouse::{cursor_over, MouseWorldPosition};
+use crate::tooltip::HoveredItem;
 
-use crate::{inventory_generic, Selection};
+use crate::currency::{BuildCost, Cost, Wallet};
+use crate::player_command::{CommandKind, CommandQueue, LocalPlayer, PlayerId, Tick};
+use crate::rarity::Rarity;
+use crate::stats::{BaseStats, Level, Modifiers};
+use crate::{inventory_generic, Selection, ITEM_VISUAL_SIZE};
 use bevy::prelude::*;
 use rand::seq::SliceRandom;
+use rand::Rng;
+use std::f32::consts::FRAC_PI_2;
 
 pub struct DebugPlugin;
 
 impl bevy::app::Plugin for DebugPlugin {
     fn build(&self, app: &mut App) {
+        app.add_event::<PlacementEvent>();
+        app.init_resource::<PressedItem>();
+        app.init_resource::<PlacementRotation>();
+        app.add_systems(Update, log_placements);
         app.add_systems(
             Update,
             (
                 clear_build_requests,
+                rotate_placement,
+                press_item,
                 click_get_out,
+            )
+                .chain()
+                .in_set(InteractionSet::World),
+        );
+        // Requests are spawned by `player_command::execute_commands`.
+        app.add_systems(
+            Update,
+            (
                 apply_deferred,
                 (
+                    find_upgrade_target,
+                    apply_deferred,
                     verify_empty_space,
+                    verify_can_afford,
                     // TODO: add more checks
                     apply_deferred,
                     react_to_build,
@@ -23,93 +49,658 @@ impl bevy::app::Plugin for DebugPlugin {
                     .run_if(component_exist::<BuildRequest>)
                     .chain(),
             )
-                .chain(),
+                .chain()
+                .in_set(InteractionSet::Resolve)
+                .in_set(BuildSet),
         );
+        app.add_systems(Update, draw_ghost.after(InteractionSet::Resolve));
     }
 }
 
+/// Checks and applies [`BuildRequest`]s, other requests of the tick are resolved after it.
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct BuildSet;
+
 #[derive(Component)]
-struct BuildRequest {
+pub(crate) struct BuildRequest {
     pub inventory: Entity,
     pub item: Entity,
     pub position: Vec2,
+    pub rotation: Quat,
 }
 
+/// Placed building under the request position, it is upgraded instead of building a new one.
+#[derive(Component)]
+struct UpgradeTarget(Entity);
+
 #[derive(Component)]
 enum RefusedBuild {
     NotEnoughPlace,
+    CannotAfford,
+    /// Only buildings of the same type as the item can be upgraded.
+    UpgradeMismatch,
 }
 
+/// World area taken by the slots of an inventory where its layout was defined, buildings can't
+/// be placed there. It doesn't follow the camera, so every peer checks the same area.
+#[derive(Component)]
+pub struct NoBuildArea(pub [Vec2; 4]);
+
+impl NoBuildArea {
+    /// Area of slots centered on `positions`, `None` without slots.
+    pub fn from_slots(positions: &[Vec3]) -> Option<Self> {
+        footprint::slots_area(positions, ITEM_VISUAL_SIZE).map(Self)
+    }
+}
+
+/// Inventory a placed building came from, where it goes back when picked up.
+#[derive(Component)]
+pub struct Origin {
+    pub inventory: Entity,
+}
+
+/// Trail of every change between inventories and the world, in order, each one holding what is
+/// needed to revert it.
+#[derive(Event, Clone, Debug)]
+pub enum PlacementEvent {
+    Placed {
+        item: Entity,
+        inventory: Entity,
+        index: usize,
+        position: Vec2,
+    },
+    /// `item` was consumed to level `target` up.
+    Upgraded {
+        item: Entity,
+        target: Entity,
+        inventory: Entity,
+        index: usize,
+    },
+    /// `item` went back into `inventory` at `index`.
+    PickedUp {
+        item: Entity,
+        inventory: Entity,
+        index: usize,
+        position: Vec2,
+    },
+    /// `item` was despawned, `refund` was earned.
+    Sold {
+        item: Entity,
+        item_type: super::ItemType,
+        level: Level,
+        rarity: Option<Rarity>,
+        modifiers: Option<Modifiers>,
+        position: Vec2,
+        refund: Cost,
+    },
+}
+
+/// Queue item under the pointer when [`Action::Build`] was pressed, it is built instead of the
+/// front item when dragged out, so a tap or click selects an item and a drag places it.
+#[derive(Resource, Default)]
+struct PressedItem(Option<Entity>);
+
+/// Rotation given to the next placed building, turned a quarter by [`Action::Rotate`].
+#[derive(Resource, Default)]
+pub struct PlacementRotation(pub Quat);
+
 fn component_exist<T: Component>(q: Query<Entity, With<T>>) -> bool {
     q.iter().next().is_some()
 }
 
+fn rotate_placement(actions: Res<ActionState>, mut rotation: ResMut<PlacementRotation>) {
+    if actions.just_pressed(Action::Rotate) {
+        rotation.0 = (Quat::from_rotation_z(FRAC_PI_2) * rotation.0).normalize();
+    }
+}
+
+/// Pressing an item also selects its inventory.
+fn press_item(
+    actions: Res<ActionState>,
+    hovered: Res<HoveredItem>,
+    mut pressed: ResMut<PressedItem>,
+    selection: Query<(&PlayerId, &Selection), With<LocalPlayer>>,
+    q_inventory: Query<(Entity, &inventory_generic::Inventory<super::ItemType>)>,
+    tick: Res<Tick>,
+    mut queue: ResMut<CommandQueue>,
+) {
+    if !actions.just_pressed(Action::Build) {
+        return;
+    }
+    pressed.0 = None;
+    let Some(item) = hovered.entity else {
+        return;
+    };
+    let Some((inventory, _)) = q_inventory.iter().find(|i| i.1.items.contains(&item)) else {
+        return;
+    };
+    pressed.0 = Some(item);
+    let Ok((player, selection)) = selection.get_single() else {
+        return;
+    };
+    if let Some(index) = selection.inventories.iter().position(|i| *i == inventory) {
+        queue.submit(*player, &tick, CommandKind::Select { inventory: index });
+    }
+}
+
+/// Releasing [`Action::Build`] builds the pressed item, or the front one, at the pointer.
+/// Released over another item of the same inventory, the pressed item moves there instead.
+#[allow(clippy::too_many_arguments)]
 fn click_get_out(
-    mut commands: Commands,
-    selection: Query<&Selection>,
-    mut q_inventory: Query<(Entity, &mut inventory_generic::Inventory<super::ItemType>)>,
-    mouse_button_input: Res<Input<MouseButton>>,
+    selection: Query<(&PlayerId, &Selection), With<LocalPlayer>>,
+    q_inventory: Query<&inventory_generic::Inventory<super::ItemType>>,
+    actions: Res<ActionState>,
     mouse_position_world: Res<MouseWorldPosition>,
+    hovered: Res<HoveredItem>,
+    mut pressed: ResMut<PressedItem>,
+    rotation: Res<PlacementRotation>,
+    tick: Res<Tick>,
+    mut queue: ResMut<CommandQueue>,
+    q_item: Query<(&super::ItemType, &Transform)>,
+    q_areas: Query<&NoBuildArea>,
 ) {
-    if mouse_button_input.just_released(MouseButton::Left) {
-        let selection = selection.single();
-        for mut i in q_inventory.iter_mut() {
-            if selection.inventories[selection.selected_index] != i.0 {
-                continue;
+    if !actions.just_released(Action::Build) {
+        return;
+    }
+    let pressed = pressed.0.take();
+    // Released where it was pressed: only a selection.
+    if pressed.is_some() && pressed == hovered.entity {
+        return;
+    }
+    let Ok((player, selection)) = selection.get_single() else {
+        return;
+    };
+    let Ok(inventory) = q_inventory.get(selection.inventories[selection.selected_index]) else {
+        return;
+    };
+    let position = |item: Option<Entity>| {
+        item.and_then(|item| inventory.items.iter().position(|i| *i == item))
+    };
+    let from = position(pressed);
+    let kind = match (from, position(hovered.entity)) {
+        (Some(from), Some(to)) => CommandKind::MoveItem {
+            inventory: selection.selected_index,
+            from,
+            to,
+        },
+        _ if inventory.items.is_empty() => return,
+        _ => {
+            let item = from.unwrap_or(0);
+            let Ok((item_type, transform)) = q_item.get(inventory.items[item]) else {
+                return;
+            };
+            let corners =
+                requested_footprint(item_type, transform, mouse_position_world.0, rotation.0);
+            if footprint::blocked(&corners, inventory_areas(&q_areas).into_iter()) {
+                info!("cannot build over an inventory");
+                return;
             }
-            let first = i.1.items.front().unwrap();
-
-            commands.spawn(BuildRequest {
-                inventory: i.0,
-                item: *first,
+            CommandKind::Build {
+                inventory: selection.selected_index,
+                item,
                 position: mouse_position_world.0,
-            });
+                rotation: rotation.0,
+            }
+        }
+    };
+    queue.submit(*player, &tick, kind);
+}
+
+fn find_upgrade_target(
+    mut commands: Commands,
+    q_requests: Query<(Entity, &BuildRequest)>,
+    q_item: Query<&super::ItemType>,
+    q_placed: Query<
+        (Entity, &super::ItemType, &GlobalTransform),
+        With<inventory_generic::MarkerPlaced>,
+    >,
+) {
+    for br in q_requests.iter() {
+        let Some((target, target_type, _)) = q_placed.iter().find(|(_, target_type, transform)| {
+            cursor_over(transform, br.1.position, target_type.footprint())
+        }) else {
+            continue;
+        };
+        if q_item.get(br.1.item).ok() == Some(target_type) {
+            commands.entity(br.0).insert(UpgradeTarget(target));
+        } else {
+            info!("cannot upgrade with a different item type");
+            commands.entity(br.0).insert(RefusedBuild::UpgradeMismatch);
         }
     }
 }
 
-fn verify_empty_space(mut commands: Commands, q_requests: Query<(Entity, &BuildRequest)>) {
+/// Corners of `item` if it was placed at `position` with `rotation`, it keeps its scale.
+fn requested_footprint(
+    item_type: &super::ItemType,
+    transform: &Transform,
+    position: Vec2,
+    rotation: Quat,
+) -> [Vec2; 4] {
+    let placed = Transform {
+        translation: position.extend(0f32),
+        rotation,
+        scale: transform.scale,
+    };
+    footprint::corners(placed.compute_affine(), item_type.footprint())
+}
+
+fn placed_footprints(
+    q_placed: &Query<
+        (Entity, &super::ItemType, &GlobalTransform),
+        With<inventory_generic::MarkerPlaced>,
+    >,
+) -> Vec<[Vec2; 4]> {
+    q_placed
+        .iter()
+        .map(|(_, item_type, transform)| {
+            footprint::corners(transform.affine(), item_type.footprint())
+        })
+        .collect()
+}
+
+fn inventory_areas(q_areas: &Query<&NoBuildArea>) -> Vec<[Vec2; 4]> {
+    q_areas.iter().map(|area| area.0).collect()
+}
+
+#[allow(clippy::type_complexity)]
+fn verify_empty_space(
+    mut commands: Commands,
+    q_requests: Query<(Entity, &BuildRequest), (Without<UpgradeTarget>, Without<RefusedBuild>)>,
+    q_item: Query<(&super::ItemType, &Transform)>,
+    q_placed: Query<
+        (Entity, &super::ItemType, &GlobalTransform),
+        With<inventory_generic::MarkerPlaced>,
+    >,
+    q_areas: Query<&NoBuildArea>,
+) {
     for br in q_requests.iter() {
-        info!("build at: {}", &br.1.position.x);
-        if (0f32..100f32).contains(&br.1.position.x) {
+        info!("build at: {}", &br.1.position);
+        let Ok((item_type, transform)) = q_item.get(br.1.item) else {
+            continue;
+        };
+        let corners = requested_footprint(item_type, transform, br.1.position, br.1.rotation);
+        let obstacles = placed_footprints(&q_placed)
+            .into_iter()
+            .chain(inventory_areas(&q_areas));
+        if footprint::blocked(&corners, obstacles) {
             info!("forbidden");
             commands.entity(br.0).insert(RefusedBuild::NotEnoughPlace);
         }
     }
 }
 
+fn verify_can_afford(
+    mut commands: Commands,
+    wallet: Res<Wallet>,
+    q_requests: Query<(Entity, &BuildRequest)>,
+    q_item: Query<&super::ItemType>,
+) {
+    for br in q_requests.iter() {
+        let Ok(item_type) = q_item.get(br.1.item) else {
+            continue;
+        };
+        let cost = item_type.build_cost();
+        if !wallet.can_afford(&cost) {
+            info!("cannot afford {}", cost.describe());
+            commands.entity(br.0).insert(RefusedBuild::CannotAfford);
+        }
+    }
+}
+
 // TODO use cmponents and check everything ok
+#[allow(clippy::too_many_arguments)]
 fn react_to_build(
     mut commands: Commands,
-    mut q_inventory: Query<&mut inventory_generic::Inventory<super::ItemType>>,
-    build_events: Query<&BuildRequest, Without<RefusedBuild>>,
+    mut q_inventory: Query<(
+        &mut inventory_generic::Inventory<super::ItemType>,
+        &mut super::queue::Upcoming,
+    )>,
+    build_events: Query<(&BuildRequest, Option<&UpgradeTarget>), Without<RefusedBuild>>,
     mut q_transform: Query<&mut Transform>,
-    mut rng: ResMut<crate::RandomDeterministic>,
+    mut q_level: Query<&mut Level>,
+    q_item: Query<&super::ItemType>,
+    mut wallet: ResMut<Wallet>,
+    mut placements: EventWriter<PlacementEvent>,
 ) {
-    for event in build_events.iter() {
-        let mut inventory = q_inventory.get_mut(event.inventory).unwrap();
-        let item_index = inventory
-            .items
-            .iter()
-            .position(|i| *i == event.item)
-            .unwrap();
+    for (event, upgrade) in build_events.iter() {
+        let Ok(item_type) = q_item.get(event.item) else {
+            continue;
+        };
+        let Ok((mut inventory, mut upcoming)) = q_inventory.get_mut(event.inventory) else {
+            continue;
+        };
+        // An earlier request of the same tick may have taken it.
+        let Some(item_index) = inventory.items.iter().position(|i| *i == event.item) else {
+            info!("item is no longer in the inventory");
+            continue;
+        };
+        // Checked by `verify_can_afford`, but several requests could share the same funds.
+        if !wallet.spend(&item_type.build_cost()) {
+            continue;
+        }
         inventory.items.remove(item_index);
-        q_transform.get_mut(event.item).unwrap().translation = event.position.extend(0f32);
-        let choices = [
-            (super::ItemType::Gun, 2),
-            (super::ItemType::Rifle, 1),
-            (super::ItemType::Aura, 1),
-        ];
-        inventory.items.push_back(
-            commands
-                .spawn(choices.choose_weighted(&mut rng.random, |i| i.1).unwrap().0)
-                .id(),
-        );
+        if let Some(UpgradeTarget(target)) = upgrade {
+            // The item is consumed by the upgrade.
+            commands.entity(event.item).despawn_recursive();
+            if let Ok(mut level) = q_level.get_mut(*target) {
+                level.0 += 1;
+            }
+            placements.send(PlacementEvent::Upgraded {
+                item: event.item,
+                target: *target,
+                inventory: event.inventory,
+                index: item_index,
+            });
+        } else {
+            if let Ok(mut transform) = q_transform.get_mut(event.item) {
+                transform.translation = event.position.extend(0f32);
+                transform.rotation = event.rotation;
+            }
+            let mut item = commands.entity(event.item);
+            item.insert((
+                inventory_generic::MarkerPlaced,
+                Origin {
+                    inventory: event.inventory,
+                },
+            ))
+            .remove::<super::queue::Locked>();
+            // Picked up buildings keep their level.
+            if !q_level.contains(event.item) {
+                item.insert(Level::default());
+            }
+            placements.send(PlacementEvent::Placed {
+                item: event.item,
+                inventory: event.inventory,
+                index: item_index,
+                position: event.position,
+            });
+        }
+        inventory.items.push_back(upcoming.pop(&mut commands));
     }
 }
 
+/// Rolls a new item for the queue, see [`super::queue::Upcoming`].
+pub(crate) fn roll_refill(rng: &mut impl Rng) -> (super::ItemType, Rarity, Modifiers) {
+    let choices = [
+        (super::ItemType::Gun, 2),
+        (super::ItemType::Rifle, 1),
+        (super::ItemType::Aura, 1),
+    ];
+    let item_type = choices.choose_weighted(rng, |i| i.1).unwrap().0;
+    let rarity = Rarity::roll(rng);
+    let modifiers = Modifiers::roll(&item_type.base_stats(), Some(rarity), rng);
+    (item_type, rarity, modifiers)
+}
+
+/// Outline of the item built when [`Action::Build`] is released, with the rotation it would get:
+/// green when it fits, yellow over a building it upgrades, red otherwise.
+#[allow(clippy::too_many_arguments)]
+fn draw_ghost(
+    mut gizmos: Gizmos,
+    actions: Res<ActionState>,
+    pressed: Res<PressedItem>,
+    hovered: Res<HoveredItem>,
+    rotation: Res<PlacementRotation>,
+    mouse_position_world: Res<MouseWorldPosition>,
+    selection: Query<&Selection, With<LocalPlayer>>,
+    q_inventory: Query<&inventory_generic::Inventory<super::ItemType>>,
+    q_item: Query<(&super::ItemType, &Transform)>,
+    q_placed: Query<
+        (Entity, &super::ItemType, &GlobalTransform),
+        With<inventory_generic::MarkerPlaced>,
+    >,
+    q_areas: Query<&NoBuildArea>,
+) {
+    if !actions.pressed(Action::Build) {
+        return;
+    }
+    // Still over the pressed item: released there, it is only a selection.
+    if pressed.0.is_some() && pressed.0 == hovered.entity {
+        return;
+    }
+    let Ok(selection) = selection.get_single() else {
+        return;
+    };
+    let Some(inventory) = selection
+        .inventories
+        .get(selection.selected_index)
+        .and_then(|inventory| q_inventory.get(*inventory).ok())
+    else {
+        return;
+    };
+    // Released over the inventory, the item moves instead.
+    if hovered.entity.is_some_and(|h| inventory.items.contains(&h)) {
+        return;
+    }
+    let Some(item) = pressed
+        .0
+        .filter(|item| inventory.items.contains(item))
+        .or(inventory.items.front().copied())
+    else {
+        return;
+    };
+    let Ok((item_type, transform)) = q_item.get(item) else {
+        return;
+    };
+    let position = mouse_position_world.0;
+    let upgrade = q_placed.iter().find(|(_, target_type, transform)| {
+        cursor_over(transform, position, target_type.footprint())
+    });
+    let color = match upgrade {
+        Some((_, target_type, _)) if target_type == item_type => Color::YELLOW,
+        Some(_) => Color::RED,
+        None => {
+            let corners = requested_footprint(item_type, transform, position, rotation.0);
+            let mut obstacles = placed_footprints(&q_placed);
+            obstacles.extend(inventory_areas(&q_areas));
+            if footprint::blocked(&corners, obstacles.into_iter()) {
+                Color::RED
+            } else {
+                Color::GREEN
+            }
+        }
+    };
+    let (_, _, angle) = rotation.0.to_euler(EulerRot::XYZ);
+    gizmos.rect_2d(
+        position,
+        angle,
+        item_type.footprint() * transform.scale.truncate(),
+        color,
+    );
+}
+
 fn clear_build_requests(mut commands: Commands, build_events: Query<Entity, With<BuildRequest>>) {
     for e in build_events.iter() {
         commands.entity(e).despawn();
     }
 }
+
+fn log_placements(mut placements: EventReader<PlacementEvent>) {
+    for placement in placements.read() {
+        match placement {
+            PlacementEvent::Placed {
+                item,
+                inventory,
+                index,
+                position,
+            } => info!("placed {item:?} from {inventory:?}[{index}] at {position}"),
+            PlacementEvent::Upgraded {
+                item,
+                target,
+                inventory,
+                index,
+            } => info!("upgraded {target:?} with {item:?} from {inventory:?}[{index}]"),
+            PlacementEvent::PickedUp {
+                item,
+                inventory,
+                index,
+                position,
+            } => info!("picked up {item:?} at {position} into {inventory:?}[{index}]"),
+            PlacementEvent::Sold {
+                item,
+                item_type,
+                level,
+                position,
+                refund,
+                ..
+            } => info!(
+                "sold {item:?} ({:?} level {}) at {position} for {}",
+                item_type,
+                level.0,
+                refund.describe()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inventory_generic::{Inventory, MarkerItemVisual};
+    use crate::tooltip::{ItemTooltipPlugin, TooltipSet};
+    use bevy::input::touch::{TouchInput, TouchPhase};
+    use bevy::input::InputPlugin;
+    use bevy::render::camera::CameraPlugin;
+
+    /// Everything between a touch and the command it submits, without rendering.
+    fn touch_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            TransformPlugin,
+            WindowPlugin::default(),
+            InputPlugin,
+            CameraPlugin,
+        ));
+        app.init_asset::<Image>();
+        app.add_plugins((
+            crate::actions::ActionsPlugin,
+            crate::simple_mouse::MousePlugin,
+            crate::tooltip::TooltipPlugin,
+            ItemTooltipPlugin::<super::super::ItemType>::default(),
+        ));
+        app.configure_sets(Update, InteractionSet::Item.after(TooltipSet::Display));
+        app.init_resource::<PressedItem>();
+        app.init_resource::<PlacementRotation>();
+        app.init_resource::<Tick>();
+        app.init_resource::<CommandQueue>();
+        app.add_systems(
+            Update,
+            (press_item, click_get_out)
+                .chain()
+                .in_set(InteractionSet::World),
+        );
+        app.world.spawn(Camera2dBundle::default());
+        app
+    }
+
+    fn touch(app: &mut App, phase: TouchPhase, position: Vec2) {
+        app.world.send_event(TouchInput {
+            phase,
+            position,
+            force: None,
+            id: 0,
+        });
+        app.update();
+    }
+
+    #[test]
+    fn tap_selects_and_drag_builds() {
+        let mut app = touch_app();
+        let item = app
+            .world
+            .spawn((
+                super::super::ItemType::Gun,
+                MarkerItemVisual,
+                TransformBundle::from_transform(
+                    Transform::from_xyz(-100f32, 0f32, 0f32).with_scale(Vec3::splat(64f32)),
+                ),
+            ))
+            .id();
+        let other = app.world.spawn(super::super::ItemType::Aura).id();
+        let inventory = app
+            .world
+            .spawn(Inventory::<super::super::ItemType> {
+                items: vec![other, item].into(),
+                ..default()
+            })
+            .id();
+        app.world.spawn((
+            Selection {
+                inventories: vec![inventory],
+                selected_index: 0,
+            },
+            PlayerId(0),
+            LocalPlayer,
+        ));
+        // Lets the camera find its window.
+        app.update();
+
+        // The default window is 1280x720, the camera is centered on the world origin.
+        let center = Vec2::new(640f32, 360f32);
+        touch(&mut app, TouchPhase::Started, center - Vec2::X * 100f32);
+        assert_eq!(app.world.resource::<HoveredItem>().entity, Some(item));
+        assert_eq!(app.world.resource::<PressedItem>().0, Some(item));
+        touch(
+            &mut app,
+            TouchPhase::Moved,
+            center + Vec2::new(150f32, -50f32),
+        );
+        // Devices move the touch to where it is lifted, the release keeps that position.
+        touch(
+            &mut app,
+            TouchPhase::Moved,
+            center + Vec2::new(200f32, -50f32),
+        );
+        touch(
+            &mut app,
+            TouchPhase::Ended,
+            center + Vec2::new(200f32, -50f32),
+        );
+
+        let queue = app.world.resource::<CommandQueue>();
+        let kinds: Vec<&CommandKind> = queue.pending.iter().map(|c| &c.kind).collect();
+        let [CommandKind::Select { inventory: 0 }, CommandKind::Build {
+            inventory: 0,
+            item: 1,
+            position,
+            ..
+        }] = kinds[..]
+        else {
+            panic!("expected a selection then a build, got {kinds:?}");
+        };
+        assert!(position.abs_diff_eq(Vec2::new(200f32, 50f32), 1e-3));
+    }
+
+    #[test]
+    fn executor_refuses_builds_over_inventory_slots() {
+        let mut app = App::new();
+        app.add_systems(Update, verify_empty_space);
+        app.world
+            .spawn(NoBuildArea::from_slots(&[Vec3::ZERO, Vec3::new(0f32, 74f32, 0f32)]).unwrap());
+        let mut request = |position: Vec2| {
+            let item = app
+                .world
+                .spawn((
+                    super::super::ItemType::Gun,
+                    Transform::from_scale(Vec3::splat(64f32)),
+                ))
+                .id();
+            app.world
+                .spawn(BuildRequest {
+                    inventory: Entity::PLACEHOLDER,
+                    item,
+                    position,
+                    rotation: Quat::IDENTITY,
+                })
+                .id()
+        };
+        let over = request(Vec2::new(20f32, 100f32));
+        let beside = request(Vec2::new(64f32, 100f32));
+        app.update();
+        assert!(app.world.get::<RefusedBuild>(over).is_some());
+        assert!(app.world.get::<RefusedBuild>(beside).is_none());
+    }
+}
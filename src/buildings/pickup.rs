@@ -0,0 +1,161 @@
+use super::behavior::{AuraEmitter, Turret};
+use super::interaction::{Origin, PlacementEvent};
+use super::ItemType;
+use crate::actions::{Action, ActionState, InteractionSet};
+use crate::crafting::craft_requested;
+use crate::currency::{BuildCost, Cost, Wallet};
+use crate::health::Health;
+use crate::inventory_generic::{Capacity, Inventory, MarkerPlaced};
+use crate::player_command::{CommandKind, CommandQueue, LocalPlayer, PlayerId, Tick};
+use crate::rarity::Rarity;
+use crate::simple_mouse::cursor_over;
+use crate::stats::{Level, Modifiers};
+use crate::tooltip::HoveredItem;
+use crate::ITEM_VISUAL_SIZE;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+/// Part of the build cost given back when selling, for each level.
+const SELL_REFUND_RATIO: f32 = 0.5f32;
+
+/// [`Action::PickUp`] on a placed building puts it back at the front of its origin inventory,
+/// [`Action::Sell`] sells it instead.
+pub struct Plugin;
+
+impl bevy::prelude::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PickUpRequest>();
+        app.add_systems(Update, submit_pick_up.in_set(InteractionSet::World));
+        // After builds and crafts of the same tick, the order is the same on every machine.
+        // Merges of adjacent items come later, with the simulation.
+        app.add_systems(
+            Update,
+            pick_up_or_sell
+                .in_set(InteractionSet::Resolve)
+                .after(super::interaction::BuildSet)
+                .after(craft_requested::<ItemType>),
+        );
+    }
+}
+
+/// Sent by [`crate::player_command`] for the placed building at `position`.
+#[derive(Event)]
+pub struct PickUpRequest {
+    pub position: Vec2,
+    pub sell: bool,
+}
+
+pub fn sell_refund(item_type: &ItemType, level: &Level) -> Cost {
+    item_type
+        .build_cost()
+        .scaled(SELL_REFUND_RATIO * level.0 as f32)
+}
+
+fn submit_pick_up(
+    actions: Res<ActionState>,
+    hovered: Res<HoveredItem>,
+    selection: Query<&PlayerId, With<LocalPlayer>>,
+    q_placed: Query<&Transform, (With<ItemType>, With<MarkerPlaced>)>,
+    tick: Res<Tick>,
+    mut queue: ResMut<CommandQueue>,
+) {
+    // Sell shares its last input with pick up, with more held.
+    let sell = actions.just_pressed(Action::Sell);
+    if !sell && !actions.just_pressed(Action::PickUp) {
+        return;
+    }
+    let (Some(item), Ok(player)) = (hovered.entity, selection.get_single()) else {
+        return;
+    };
+    let Ok(transform) = q_placed.get(item) else {
+        return;
+    };
+    // Its center, the same building on every machine.
+    let position = transform.translation.truncate();
+    let kind = if sell {
+        CommandKind::Sell { position }
+    } else {
+        CommandKind::PickUp { position }
+    };
+    queue.submit(*player, &tick, kind);
+}
+
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+pub(super) fn pick_up_or_sell(
+    mut commands: Commands,
+    mut requests: EventReader<PickUpRequest>,
+    mut wallet: ResMut<Wallet>,
+    mut q_placed: Query<
+        (
+            Entity,
+            &ItemType,
+            &Origin,
+            &mut Transform,
+            Option<&Level>,
+            Option<&Rarity>,
+            Option<&Modifiers>,
+        ),
+        With<MarkerPlaced>,
+    >,
+    mut q_inventory: Query<(&mut Inventory<ItemType>, Option<&Capacity>)>,
+    mut placements: EventWriter<PlacementEvent>,
+) {
+    // Removals are deferred, a building requested twice in a tick is only handled once.
+    let mut handled = HashSet::new();
+    for request in requests.read() {
+        let found = q_placed
+            .iter_mut()
+            .find(|(item, item_type, _, transform, ..)| {
+                !handled.contains(item)
+                    && cursor_over(
+                        &GlobalTransform::from(**transform),
+                        request.position,
+                        item_type.footprint(),
+                    )
+            });
+        let Some((item, item_type, origin, mut transform, level, rarity, modifiers)) = found else {
+            continue;
+        };
+        let position = transform.translation.truncate();
+        if request.sell {
+            let level = level.copied().unwrap_or_default();
+            let refund = sell_refund(item_type, &level);
+            wallet.earn(&refund);
+            commands.entity(item).despawn_recursive();
+            handled.insert(item);
+            placements.send(PlacementEvent::Sold {
+                item,
+                item_type: *item_type,
+                level,
+                rarity: rarity.copied(),
+                modifiers: modifiers.cloned(),
+                position,
+                refund,
+            });
+            continue;
+        }
+        let Ok((mut inventory, capacity)) = q_inventory.get_mut(origin.inventory) else {
+            continue;
+        };
+        if capacity.is_some_and(|c| inventory.items.len() >= c.0) {
+            info!("inventory full");
+            continue;
+        }
+        // In front, so it is the next one to build.
+        inventory.items.push_front(item);
+        transform.rotation = Quat::IDENTITY;
+        // Its level shows again once placed.
+        transform.scale = Vec3::splat(ITEM_VISUAL_SIZE);
+        commands
+            .entity(item)
+            .remove::<(MarkerPlaced, Origin, Health, Turret, AuraEmitter)>();
+        handled.insert(item);
+        placements.send(PlacementEvent::PickedUp {
+            item,
+            inventory: origin.inventory,
+            index: 0,
+            position,
+        });
+    }
+}
@@ -0,0 +1,294 @@
+use super::interaction::roll_refill;
+use super::ItemType;
+use crate::actions::{Action, ActionState, InteractionSet};
+use crate::crafting::{Crafted, CraftingSet};
+use crate::currency::{Cost, Currency, Wallet};
+use crate::inventory_generic::{
+    CommandVisualBuilder, Inventory, InventoryLayoutSet, InventoryVisualDef, MarkerItemVisual,
+};
+use crate::player_command::{
+    CommandKind, CommandQueue, LocalPlayer, PlayerId, SimulationSet, Tick,
+};
+use crate::tooltip::HoveredItem;
+use crate::{Selection, ITEM_VISUAL_SIZE};
+use bevy::prelude::*;
+use rand_chacha::ChaCha20Rng;
+use std::collections::VecDeque;
+
+/// Upcoming items are drawn smaller than the queue ones.
+const UPCOMING_SCALE: f32 = 0.6f32;
+
+/// Explicit actions on the selected building queue:
+/// - [`Action::Discard`] discards the hovered item, or the front one.
+/// - [`Action::Reroll`] rerolls every unlocked item, for [`reroll_cost`].
+/// - [`Action::ToggleLock`] toggles the lock of the hovered item.
+///
+/// Items entering the queue come from its [`Upcoming`] buffer, shown after the visible slots,
+/// including the one taking the slot freed by crafting.
+pub struct Plugin;
+
+impl bevy::prelude::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (toggle_lock, discard, reroll, fill_upcoming)
+                .chain()
+                .in_set(InteractionSet::World),
+        );
+        app.add_systems(
+            Update,
+            refill_crafted.after(CraftingSet).in_set(SimulationSet),
+        );
+        app.add_systems(Update, draw_locks);
+        app.add_systems(PostUpdate, reposition_upcoming.in_set(InventoryLayoutSet));
+    }
+}
+
+/// Pre-rolled items which will enter the queue next, in order.
+///
+/// It has its own random stream so the preview doesn't change when other systems roll.
+#[derive(Component)]
+pub struct Upcoming {
+    pub items: VecDeque<Entity>,
+    /// How many items are rolled in advance.
+    pub look_ahead: usize,
+    pub rng: ChaCha20Rng,
+}
+
+impl Upcoming {
+    pub fn new(look_ahead: usize, rng: ChaCha20Rng) -> Self {
+        Self {
+            items: Default::default(),
+            look_ahead,
+            rng,
+        }
+    }
+
+    /// Takes the next item for the queue, rolling it right away if the buffer is empty.
+    pub fn pop(&mut self, commands: &mut Commands) -> Entity {
+        match self.items.pop_front() {
+            Some(item) => {
+                commands
+                    .entity(item)
+                    .remove::<MarkerUpcoming>()
+                    .insert(Transform::from_scale(Vec3::splat(ITEM_VISUAL_SIZE)));
+                item
+            }
+            None => commands.spawn(roll_refill(&mut self.rng)).id(),
+        }
+    }
+}
+
+/// Item in an [`Upcoming`] buffer, not part of `Inventory::items` yet.
+#[derive(Component)]
+pub struct MarkerUpcoming;
+
+/// Item kept in place when rerolling the queue.
+#[derive(Component)]
+pub struct Locked;
+
+pub fn reroll_cost() -> Cost {
+    Cost(vec![(Currency::Gold, 5)])
+}
+
+/// Local player, with the index of the selected inventory and its entity.
+fn selected_inventory(
+    selection: &Query<(&PlayerId, &Selection), With<LocalPlayer>>,
+) -> Option<(PlayerId, usize, Entity)> {
+    let (player, selection) = selection.get_single().ok()?;
+    let inventory = *selection.inventories.get(selection.selected_index)?;
+    Some((*player, selection.selected_index, inventory))
+}
+
+fn toggle_lock(
+    actions: Res<ActionState>,
+    hovered: Res<HoveredItem>,
+    selection: Query<(&PlayerId, &Selection), With<LocalPlayer>>,
+    q_inventory: Query<&Inventory<ItemType>>,
+    tick: Res<Tick>,
+    mut queue: ResMut<CommandQueue>,
+) {
+    if !actions.just_pressed(Action::ToggleLock) {
+        return;
+    }
+    let (Some(item), Ok((player, selection))) = (hovered.entity, selection.get_single()) else {
+        return;
+    };
+    // Only items still in a queue can be locked.
+    let found = selection
+        .inventories
+        .iter()
+        .enumerate()
+        .find_map(|(index, e)| {
+            let items = &q_inventory.get(*e).ok()?.items;
+            Some((index, items.iter().position(|i| *i == item)?))
+        });
+    if let Some((inventory, item)) = found {
+        queue.submit(*player, &tick, CommandKind::ToggleLock { inventory, item });
+    }
+}
+
+fn discard(
+    actions: Res<ActionState>,
+    selection: Query<(&PlayerId, &Selection), With<LocalPlayer>>,
+    hovered: Res<HoveredItem>,
+    q_inventory: Query<&Inventory<ItemType>, With<Upcoming>>,
+    tick: Res<Tick>,
+    mut queue: ResMut<CommandQueue>,
+) {
+    if !actions.just_pressed(Action::Discard) {
+        return;
+    }
+    let Ok((player, selection)) = selection.get_single() else {
+        return;
+    };
+    let Some(Ok(inventory)) = selection
+        .inventories
+        .get(selection.selected_index)
+        .map(|e| q_inventory.get(*e))
+    else {
+        return;
+    };
+    let item = hovered
+        .entity
+        .and_then(|hovered| inventory.items.iter().position(|i| *i == hovered))
+        .unwrap_or(0);
+    queue.submit(
+        *player,
+        &tick,
+        CommandKind::Discard {
+            inventory: selection.selected_index,
+            item,
+        },
+    );
+}
+
+/// Discards the item at `index`, the queue is refilled from `upcoming`.
+pub fn discard_at(
+    commands: &mut Commands,
+    inventory: &mut Inventory<ItemType>,
+    upcoming: &mut Upcoming,
+    index: usize,
+) {
+    let Some(item) = inventory.items.remove(index) else {
+        return;
+    };
+    commands.entity(item).despawn_recursive();
+    inventory.items.push_back(upcoming.pop(commands));
+}
+
+fn reroll(
+    actions: Res<ActionState>,
+    selection: Query<(&PlayerId, &Selection), With<LocalPlayer>>,
+    q_upcoming: Query<(), With<Upcoming>>,
+    tick: Res<Tick>,
+    mut queue: ResMut<CommandQueue>,
+) {
+    if !actions.just_pressed(Action::Reroll) {
+        return;
+    }
+    let Some((player, inventory, entity)) = selected_inventory(&selection) else {
+        return;
+    };
+    if q_upcoming.contains(entity) {
+        queue.submit(player, &tick, CommandKind::Reroll { inventory });
+    }
+}
+
+/// Replaces every item for which `is_kept` is false with the next upcoming one, for
+/// [`reroll_cost`].
+pub fn reroll_unlocked(
+    commands: &mut Commands,
+    wallet: &mut Wallet,
+    inventory: &mut Inventory<ItemType>,
+    upcoming: &mut Upcoming,
+    is_kept: impl Fn(Entity) -> bool,
+) {
+    let cost = reroll_cost();
+    if !wallet.spend(&cost) {
+        info!("cannot afford reroll: {}", cost.describe());
+        return;
+    }
+    for item in inventory.items.iter_mut() {
+        if is_kept(*item) {
+            continue;
+        }
+        commands.entity(*item).despawn_recursive();
+        *item = upcoming.pop(commands);
+    }
+}
+
+/// Two items merged into one, the queue keeps its length.
+fn refill_crafted(
+    mut commands: Commands,
+    mut crafted: EventReader<Crafted>,
+    mut q_inventory: Query<(&mut Inventory<ItemType>, &mut Upcoming)>,
+) {
+    for event in crafted.read() {
+        if let Ok((mut inventory, mut upcoming)) = q_inventory.get_mut(event.inventory) {
+            inventory.items.push_back(upcoming.pop(&mut commands));
+        }
+    }
+}
+
+/// Rolls upcoming items until `look_ahead` of them are known.
+fn fill_upcoming(mut commands: Commands, mut q_upcoming: Query<&mut Upcoming>) {
+    for mut upcoming in q_upcoming.iter_mut() {
+        while upcoming.items.len() < upcoming.look_ahead {
+            let (item_type, rarity, modifiers) = roll_refill(&mut upcoming.rng);
+            let visual = item_type.command_to_create_visual();
+            let item = commands
+                .spawn((item_type, rarity, modifiers))
+                .add(visual)
+                .insert((MarkerItemVisual, MarkerUpcoming))
+                .id();
+            upcoming.items.push_back(item);
+        }
+    }
+}
+
+/// Lays upcoming items after the last visible slot, following the direction of the slots.
+#[allow(clippy::type_complexity)]
+fn reposition_upcoming(
+    q_upcoming: Query<
+        (&Upcoming, &InventoryVisualDef),
+        Or<(Changed<Upcoming>, Changed<InventoryVisualDef>)>,
+    >,
+    mut q_transform: Query<&mut Transform, With<MarkerUpcoming>>,
+) {
+    for (upcoming, visual_def) in q_upcoming.iter() {
+        let Some(last) = visual_def.positions.last() else {
+            continue;
+        };
+        let step = match visual_def.positions.len() {
+            0 | 1 => Vec3::Y * (ITEM_VISUAL_SIZE + 10f32),
+            len => *last - visual_def.positions[len - 2],
+        };
+        // The first preview sits closer, as previews are smaller than the slots.
+        let start = *last + step * (1f32 + UPCOMING_SCALE) / 2f32;
+        for (i, item) in upcoming.items.iter().enumerate() {
+            let Ok(mut transform) = q_transform.get_mut(*item) else {
+                continue;
+            };
+            transform.translation = start + step * UPCOMING_SCALE * i as f32;
+            transform.scale = Vec3::splat(ITEM_VISUAL_SIZE * UPCOMING_SCALE);
+        }
+    }
+}
+
+fn draw_locks(
+    mut gizmos: Gizmos,
+    q_locked: Query<(&GlobalTransform, &ViewVisibility), With<Locked>>,
+) {
+    for (transform, visibility) in q_locked.iter() {
+        if !visibility.get() {
+            continue;
+        }
+        gizmos.rect_2d(
+            transform.translation().truncate(),
+            0f32,
+            Vec2::splat(ITEM_VISUAL_SIZE + 8f32),
+            Color::WHITE,
+        );
+    }
+}
@@ -0,0 +1,355 @@
+use crate::actions::{Action, ActionState};
+use crate::inventory_generic::{InventoryLayoutSet, InventoryVisualDef};
+use crate::simple_mouse::{CameraCursors, MainCamera};
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+use bevy::render::camera::CameraUpdateSystem;
+use bevy::transform::TransformSystem;
+use bevy::window::PrimaryWindow;
+
+#[derive(Resource)]
+pub struct CameraSettings {
+    /// Limits of the main camera projection scale, below 1 zooms in.
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// Scale change for each wheel line.
+    pub zoom_step: f32,
+    /// Logical pixels per second when panning with keys or edge scrolling.
+    pub pan_speed: f32,
+    /// Cursor distance to the window border starting edge scrolling, 0 disables it.
+    pub edge_scroll_margin: f32,
+    /// World area the camera center stays in.
+    pub bounds: Option<Rect>,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            min_scale: 0.5f32,
+            max_scale: 3f32,
+            zoom_step: 0.1f32,
+            pan_speed: 600f32,
+            edge_scroll_margin: 10f32,
+            bounds: None,
+        }
+    }
+}
+
+/// Inventory staying at the same place on screen: `offsets` are its slots in logical pixels from
+/// the center of `camera` at zoom 1, [`InventoryVisualDef::positions`] follow that camera.
+#[derive(Component)]
+pub struct ScreenAnchored {
+    /// Linked to the first rendered [`MainCamera`] when `None`.
+    pub camera: Option<Entity>,
+    pub offsets: Vec<Vec3>,
+}
+
+/// Camera moved by pan and zoom: the one under the cursor, else the first rendered.
+fn controlled_camera(
+    cursors: &CameraCursors,
+    cameras: impl Iterator<Item = (Entity, isize)>,
+) -> Option<Entity> {
+    let mut first = None;
+    for (entity, order) in cameras {
+        if cursors.hovered_camera == Some(entity) {
+            return Some(entity);
+        }
+        if first.is_none_or(|(_, first_order)| order < first_order) {
+            first = Some((entity, order));
+        }
+    }
+    first.map(|(entity, _)| entity)
+}
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraSettings>();
+        // Moved before transforms and projections are updated, so the cursor is mapped with
+        // the camera as it is rendered.
+        app.add_systems(
+            PostUpdate,
+            (
+                (pan_camera, zoom_camera, pinch_zoom, clamp_camera).chain(),
+                anchor_inventories.before(InventoryLayoutSet),
+            )
+                .chain()
+                .before(TransformSystem::TransformPropagate)
+                .before(CameraUpdateSystem),
+        );
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn pan_camera(
+    time: Res<Time>,
+    settings: Res<CameraSettings>,
+    actions: Res<ActionState>,
+    cursors: Res<CameraCursors>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    mut last_cursor: Local<Option<Vec2>>,
+    mut q_camera: Query<
+        (Entity, &Camera, &mut Transform, &OrthographicProjection),
+        With<MainCamera>,
+    >,
+) {
+    let cursor = q_window.get_single().ok().and_then(|window| {
+        let size = Vec2::new(window.width(), window.height());
+        window
+            .cursor_position()
+            .map(|cursor| (cursor, size, window.focused))
+    });
+    // Screen direction, y up.
+    let mut direction = Vec2::ZERO;
+    for (action, step) in [
+        (Action::PanUp, Vec2::Y),
+        (Action::PanDown, Vec2::NEG_Y),
+        (Action::PanLeft, Vec2::NEG_X),
+        (Action::PanRight, Vec2::X),
+    ] {
+        if actions.pressed(action) {
+            direction += step;
+        }
+    }
+    if let Some((cursor, size, true)) = cursor {
+        let margin = settings.edge_scroll_margin;
+        if margin > 0f32 {
+            if cursor.x < margin {
+                direction.x -= 1f32;
+            } else if cursor.x > size.x - margin {
+                direction.x += 1f32;
+            }
+            // Window y goes down.
+            if cursor.y < margin {
+                direction.y += 1f32;
+            } else if cursor.y > size.y - margin {
+                direction.y -= 1f32;
+            }
+        }
+    }
+    let mut delta = direction.normalize_or_zero() * settings.pan_speed * time.delta_seconds();
+    // Dragging moves the world along with the cursor.
+    let cursor = cursor.map(|(cursor, _, _)| cursor);
+    if actions.pressed(Action::DragPan) {
+        if let (Some(cursor), Some(last)) = (cursor, *last_cursor) {
+            let moved = cursor - last;
+            delta += Vec2::new(-moved.x, moved.y);
+        }
+    }
+    *last_cursor = cursor;
+    if delta == Vec2::ZERO {
+        return;
+    }
+    let cameras = q_camera
+        .iter()
+        .map(|(entity, camera, ..)| (entity, camera.order));
+    let Some(camera) = controlled_camera(&cursors, cameras) else {
+        return;
+    };
+    if let Ok((_, _, mut transform, projection)) = q_camera.get_mut(camera) {
+        transform.translation += (delta * projection.scale).extend(0f32);
+    }
+}
+
+/// Zooms around the cursor, the world position under it stays the same.
+#[allow(clippy::type_complexity)]
+fn zoom_camera(
+    settings: Res<CameraSettings>,
+    mut wheel: EventReader<MouseWheel>,
+    cursors: Res<CameraCursors>,
+    mut q_camera: Query<
+        (
+            Entity,
+            &Camera,
+            &GlobalTransform,
+            &mut Transform,
+            &mut OrthographicProjection,
+        ),
+        With<MainCamera>,
+    >,
+) {
+    let lines: f32 = wheel
+        .read()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            // Roughly one line.
+            MouseScrollUnit::Pixel => event.y / 16f32,
+        })
+        .sum();
+    if lines == 0f32 {
+        return;
+    }
+    let cameras = q_camera
+        .iter()
+        .map(|(entity, camera, ..)| (entity, camera.order));
+    let Some(camera) = controlled_camera(&cursors, cameras) else {
+        return;
+    };
+    let Ok((_, _, global_transform, mut transform, mut projection)) = q_camera.get_mut(camera)
+    else {
+        return;
+    };
+    let scale = (projection.scale * (1f32 - lines * settings.zoom_step))
+        .clamp(settings.min_scale, settings.max_scale);
+    // World distance from the camera center to the cursor, it shrinks with the scale.
+    let offset = cursors
+        .world_positions
+        .get(&camera)
+        .map(|cursor| *cursor - global_transform.translation().truncate())
+        .unwrap_or_default();
+    transform.translation += (offset * (1f32 - scale / projection.scale)).extend(0f32);
+    projection.scale = scale;
+}
+
+/// Two fingers moving apart zoom in, closer zoom out.
+fn pinch_zoom(
+    settings: Res<CameraSettings>,
+    touches: Res<Touches>,
+    cursors: Res<CameraCursors>,
+    mut q_projection: Query<(Entity, &Camera, &mut OrthographicProjection), With<MainCamera>>,
+) {
+    let mut fingers = touches.iter();
+    let (Some(a), Some(b)) = (fingers.next(), fingers.next()) else {
+        return;
+    };
+    let previous = a.previous_position().distance(b.previous_position());
+    let current = a.position().distance(b.position());
+    if previous <= 0f32 || current <= 0f32 {
+        return;
+    }
+    let cameras = q_projection
+        .iter()
+        .map(|(entity, camera, _)| (entity, camera.order));
+    let Some(camera) = controlled_camera(&cursors, cameras) else {
+        return;
+    };
+    if let Ok((_, _, mut projection)) = q_projection.get_mut(camera) {
+        projection.scale =
+            (projection.scale * previous / current).clamp(settings.min_scale, settings.max_scale);
+    }
+}
+
+fn clamp_camera(
+    settings: Res<CameraSettings>,
+    mut q_camera: Query<&mut Transform, (With<MainCamera>, Changed<Transform>)>,
+) {
+    let Some(bounds) = settings.bounds else {
+        return;
+    };
+    for mut transform in q_camera.iter_mut() {
+        let clamped = transform
+            .translation
+            .truncate()
+            .clamp(bounds.min, bounds.max);
+        if clamped != transform.translation.truncate() {
+            transform.translation = clamped.extend(transform.translation.z);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn anchor_inventories(
+    q_camera: Query<
+        (Entity, &Camera, Ref<Transform>, Ref<OrthographicProjection>),
+        (With<MainCamera>, Without<ScreenAnchored>),
+    >,
+    mut q_inventory: Query<(&mut ScreenAnchored, &mut InventoryVisualDef)>,
+) {
+    for (mut anchored, mut visual_def) in q_inventory.iter_mut() {
+        if anchored.camera.is_none() {
+            anchored.camera = q_camera
+                .iter()
+                .min_by_key(|(_, camera, ..)| camera.order)
+                .map(|(entity, ..)| entity);
+        }
+        let Some(Ok((_, _, transform, projection))) = anchored.camera.map(|c| q_camera.get(c))
+        else {
+            continue;
+        };
+        if !transform.is_changed() && !projection.is_changed() && !anchored.is_changed() {
+            continue;
+        }
+        let Some(first) = anchored.offsets.first() else {
+            continue;
+        };
+        // Only the first slot is pinned to the screen, the others keep their distance in the
+        // world so items, which zoom with it, don't overlap.
+        let anchor = transform.translation.truncate().extend(0f32)
+            + *first * Vec3::new(projection.scale, projection.scale, 1f32);
+        visual_def.positions = anchored
+            .offsets
+            .iter()
+            .map(|offset| anchor + *offset - *first)
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_camera(app: &mut App, order: isize) -> Entity {
+        app.world
+            .spawn((
+                Camera { order, ..default() },
+                Transform::default(),
+                OrthographicProjection::default(),
+                MainCamera,
+            ))
+            .id()
+    }
+
+    #[test]
+    fn inventories_follow_their_camera() {
+        let mut app = App::new();
+        app.add_systems(Update, anchor_inventories);
+        let minimap = spawn_camera(&mut app, 1);
+        let main = spawn_camera(&mut app, 0);
+        let inventory = app
+            .world
+            .spawn((
+                ScreenAnchored {
+                    camera: None,
+                    offsets: vec![Vec3::new(100f32, 0f32, 0f32)],
+                },
+                InventoryVisualDef { positions: vec![] },
+            ))
+            .id();
+        let positions = |app: &App| {
+            app.world
+                .get::<InventoryVisualDef>(inventory)
+                .unwrap()
+                .positions
+                .clone()
+        };
+        app.update();
+        assert_eq!(
+            app.world.get::<ScreenAnchored>(inventory).unwrap().camera,
+            Some(main)
+        );
+        assert_eq!(positions(&app), vec![Vec3::new(100f32, 0f32, 0f32)]);
+
+        app.world
+            .get_mut::<Transform>(minimap)
+            .unwrap()
+            .translation
+            .x = 500f32;
+        app.update();
+        assert_eq!(positions(&app), vec![Vec3::new(100f32, 0f32, 0f32)]);
+
+        app.world.get_mut::<Transform>(main).unwrap().translation.x = 50f32;
+        app.update();
+        assert_eq!(positions(&app), vec![Vec3::new(150f32, 0f32, 0f32)]);
+    }
+
+    #[test]
+    fn controls_the_camera_under_the_cursor() {
+        let [a, b] = [Entity::from_raw(0), Entity::from_raw(1)];
+        let mut cursors = CameraCursors::default();
+        let cameras = || [(a, 1), (b, 0)].into_iter();
+        assert_eq!(controlled_camera(&cursors, cameras()), Some(b));
+        cursors.hovered_camera = Some(a);
+        assert_eq!(controlled_camera(&cursors, cameras()), Some(a));
+    }
+}
@@ -0,0 +1,493 @@
+use crate::actions::{Action, ActionState, InteractionSet};
+use crate::buildings::queue::Locked;
+use crate::inventory_generic::{CommandVisualBuilder, Inventory};
+use crate::player_command::{
+    CommandKind, CommandQueue, LocalPlayer, PlayerId, SimulationSet, Tick,
+};
+use crate::rarity::Rarity;
+use crate::ron_asset::RonAssetLoader;
+use crate::stats::{BaseStats, Level, Modifiers};
+use crate::tooltip::HoveredItem;
+use crate::Selection;
+use bevy::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+use std::marker::PhantomData;
+
+/// Item types [`CraftingPlugin`] can merge.
+pub trait Craftable:
+    Component
+    + CommandVisualBuilder
+    + BaseStats
+    + TypePath
+    + for<'de> Deserialize<'de>
+    + Copy
+    + PartialEq
+{
+}
+
+impl<IT> Craftable for IT where
+    IT: Component
+        + CommandVisualBuilder
+        + BaseStats
+        + TypePath
+        + for<'de> Deserialize<'de>
+        + Copy
+        + PartialEq
+{
+}
+
+/// Two items combining into a new one, the order of `inputs` doesn't matter.
+#[derive(Clone, Deserialize)]
+pub struct Recipe<IT> {
+    pub inputs: [IT; 2],
+    pub output: IT,
+}
+
+impl<IT: PartialEq> Recipe<IT> {
+    pub fn matches(&self, a: &IT, b: &IT) -> bool {
+        let [first, second] = &self.inputs;
+        (first == a && second == b) || (first == b && second == a)
+    }
+}
+
+/// Every recipe of an item type, the first matching one is used.
+///
+/// Loaded from the RON file given to [`CraftingPlugin`], there are none until it is loaded.
+#[derive(Asset, TypePath, Resource, Deserialize, Clone)]
+#[serde(bound = "")]
+pub struct Recipes<IT: Craftable> {
+    pub recipes: Vec<Recipe<IT>>,
+}
+
+impl<IT: Craftable> Default for Recipes<IT> {
+    fn default() -> Self {
+        Self {
+            recipes: Default::default(),
+        }
+    }
+}
+
+impl<IT: Craftable> Recipes<IT> {
+    pub fn find(&self, a: &IT, b: &IT) -> Option<&IT> {
+        self.recipes
+            .iter()
+            .find(|recipe| recipe.matches(a, b))
+            .map(|recipe| &recipe.output)
+    }
+}
+
+#[derive(Resource)]
+struct RecipesHandle<IT: Craftable>(Handle<Recipes<IT>>);
+
+/// Sent when two items of `inventory` were merged into one, it has one item less.
+#[derive(Event)]
+pub struct Crafted {
+    pub inventory: Entity,
+}
+
+/// Sent by [`crate::player_command`] to merge `source` into `target`, both in `inventory`.
+#[derive(Event)]
+pub struct CraftRequest {
+    pub inventory: Entity,
+    pub source: Entity,
+    pub target: Entity,
+}
+
+/// Merging systems, [`Crafted`] events are sent by this set.
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct CraftingSet;
+
+/// Item being dragged with [`Action::Build`], it is merged into the item it is released on.
+#[derive(Resource)]
+struct Dragged<IT> {
+    item: Option<Entity>,
+    _item_type: PhantomData<IT>,
+}
+
+impl<IT> Default for Dragged<IT> {
+    fn default() -> Self {
+        Self {
+            item: None,
+            _item_type: Default::default(),
+        }
+    }
+}
+
+/// Merges items of an [`Inventory<IT>`] following [`Recipes<IT>`]:
+/// - matching items next to each other merge right away, unless one of them is [`Locked`] or
+///   was upgraded,
+/// - dragging an item onto a matching one of the same inventory submits
+///   [`CommandKind::Craft`], which merges them.
+///
+/// The crafted item takes the place of the first input, with the best rarity and [`Level`] of
+/// both inputs and affixes rolled for it. Inputs are despawned and the inventory is modified like any other
+/// change, so visuals and positions are updated by
+/// [`crate::inventory_generic::InventoryPlugin`]. Inventories refill the freed slot on
+/// [`Crafted`].
+pub struct CraftingPlugin<IT: Craftable> {
+    /// RON file with the [`Recipes<IT>`], in the assets folder.
+    pub path: &'static str,
+    _item_type: PhantomData<IT>,
+}
+
+impl<IT: Craftable> CraftingPlugin<IT> {
+    pub fn new(path: &'static str) -> Self {
+        Self {
+            path,
+            _item_type: Default::default(),
+        }
+    }
+}
+
+impl<IT: Craftable> Plugin for CraftingPlugin<IT> {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Recipes<IT>>();
+        app.register_asset_loader(RonAssetLoader::<Recipes<IT>>::new(&["recipes.ron"]));
+        app.init_resource::<Recipes<IT>>();
+        app.init_resource::<Dragged<IT>>();
+        app.add_event::<Crafted>();
+        app.add_event::<CraftRequest>();
+        let path = self.path;
+        app.add_systems(
+            Startup,
+            move |mut commands: Commands, asset_server: Res<AssetServer>| {
+                commands.insert_resource(RecipesHandle::<IT>(asset_server.load(path)));
+            },
+        );
+        app.add_systems(PreUpdate, apply_loaded_recipes::<IT>);
+        // Before the build systems, so a drop onto an item doesn't also build it.
+        app.add_systems(Update, drag_onto::<IT>.in_set(InteractionSet::Item));
+        app.add_systems(
+            Update,
+            craft_requested::<IT>
+                .in_set(InteractionSet::Resolve)
+                .in_set(CraftingSet),
+        );
+        app.add_systems(
+            Update,
+            merge_adjacent::<IT>
+                .in_set(CraftingSet)
+                .in_set(SimulationSet),
+        );
+    }
+}
+
+/// Also applies changes of the file while the game runs, when hot reloading is enabled.
+fn apply_loaded_recipes<IT: Craftable>(
+    mut events: EventReader<AssetEvent<Recipes<IT>>>,
+    handle: Option<Res<RecipesHandle<IT>>>,
+    assets: Res<Assets<Recipes<IT>>>,
+    mut recipes: ResMut<Recipes<IT>>,
+) {
+    let Some(handle) = handle else {
+        return;
+    };
+    for event in events.read() {
+        if !event.is_loaded_with_dependencies(&handle.0) && !event.is_modified(&handle.0) {
+            continue;
+        }
+        if let Some(loaded) = assets.get(&handle.0) {
+            *recipes = loaded.clone();
+            info!("{} recipes loaded", recipes.recipes.len());
+        }
+    }
+}
+
+/// Replaces the items at `first` and `second` with `output`, at the place of `first`.
+#[allow(clippy::too_many_arguments)]
+fn merge<IT: Craftable>(
+    commands: &mut Commands,
+    rng: &mut impl Rng,
+    inventory: &mut Inventory<IT>,
+    first: usize,
+    second: usize,
+    output: IT,
+    rarity: Option<Rarity>,
+    level: Option<Level>,
+) {
+    let consumed = [inventory.items[first], inventory.items[second]];
+    let modifiers = Modifiers::roll(&output.base_stats(), rarity, rng);
+    let crafted = commands.spawn((output, modifiers)).id();
+    if let Some(rarity) = rarity {
+        commands.entity(crafted).insert(rarity);
+    }
+    if let Some(level) = level {
+        commands.entity(crafted).insert(level);
+    }
+    inventory.items[first] = crafted;
+    inventory.items.remove(second);
+    for item in consumed {
+        commands.entity(item).despawn_recursive();
+    }
+}
+
+fn best_rarity(q_rarity: &Query<&Rarity>, items: [Entity; 2]) -> Option<Rarity> {
+    items
+        .iter()
+        .filter_map(|i| q_rarity.get(*i).ok())
+        .max()
+        .copied()
+}
+
+fn best_level(q_level: &Query<&Level>, items: [Entity; 2]) -> Option<Level> {
+    items
+        .iter()
+        .filter_map(|i| q_level.get(*i).ok())
+        .max_by_key(|level| level.0)
+        .copied()
+}
+
+fn stream_name<IT>() -> String {
+    format!("crafting/{}", std::any::type_name::<IT>())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn merge_adjacent<IT: Craftable>(
+    mut commands: Commands,
+    recipes: Res<Recipes<IT>>,
+    mut rng: ResMut<crate::RandomDeterministic>,
+    mut crafted: EventWriter<Crafted>,
+    mut q_inventory: Query<(Entity, &mut Inventory<IT>), Changed<Inventory<IT>>>,
+    q_item: Query<&IT>,
+    q_rarity: Query<&Rarity>,
+    q_level: Query<&Level>,
+    q_locked: Query<(), With<Locked>>,
+) {
+    let rng = rng.stream(&stream_name::<IT>());
+    // Only merged on purpose, with a drag.
+    let kept =
+        |item: Entity| q_locked.contains(item) || q_level.get(item).is_ok_and(|level| level.0 > 1);
+    for (entity, mut inventory) in q_inventory.iter_mut() {
+        let mut index = 0;
+        while index + 1 < inventory.items.len() {
+            let pair = [inventory.items[index], inventory.items[index + 1]];
+            let output = match (q_item.get(pair[0]), q_item.get(pair[1])) {
+                (Ok(a), Ok(b)) if !kept(pair[0]) && !kept(pair[1]) => recipes.find(a, b).copied(),
+                _ => None,
+            };
+            let Some(output) = output else {
+                index += 1;
+                continue;
+            };
+            merge(
+                &mut commands,
+                rng,
+                &mut inventory,
+                index,
+                index + 1,
+                output,
+                best_rarity(&q_rarity, pair),
+                best_level(&q_level, pair),
+            );
+            crafted.send(Crafted { inventory: entity });
+            // The crafted item is only spawned at the end of the frame, it can merge with its
+            // new neighbours next frame as the inventory changed.
+            index += 1;
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn drag_onto<IT: Craftable>(
+    mut actions: ResMut<ActionState>,
+    hovered: Res<HoveredItem>,
+    recipes: Res<Recipes<IT>>,
+    mut dragged: ResMut<Dragged<IT>>,
+    selection: Query<(&PlayerId, &Selection), With<LocalPlayer>>,
+    q_inventory: Query<&Inventory<IT>>,
+    q_item: Query<&IT>,
+    tick: Res<Tick>,
+    mut queue: ResMut<CommandQueue>,
+) {
+    if actions.just_pressed(Action::Cancel) {
+        dragged.item = None;
+    }
+    if actions.just_pressed(Action::Build) {
+        dragged.item = hovered
+            .entity
+            .filter(|item| q_inventory.iter().any(|i| i.items.contains(item)));
+    }
+    if !actions.just_released(Action::Build) {
+        return;
+    }
+    let (Some(source), Some(target)) = (dragged.item.take(), hovered.entity) else {
+        return;
+    };
+    if source == target {
+        return;
+    }
+    let (Ok(a), Ok(b)) = (q_item.get(source), q_item.get(target)) else {
+        return;
+    };
+    if recipes.find(a, b).is_none() {
+        return;
+    }
+    let Ok((player, selection)) = selection.get_single() else {
+        return;
+    };
+    for (index, inventory) in selection.inventories.iter().enumerate() {
+        let Ok(inventory) = q_inventory.get(*inventory) else {
+            continue;
+        };
+        let position = |item: Entity| inventory.items.iter().position(|i| *i == item);
+        let (Some(from), Some(onto)) = (position(source), position(target)) else {
+            continue;
+        };
+        queue.submit(
+            *player,
+            &tick,
+            CommandKind::Craft {
+                inventory: index,
+                from,
+                onto,
+            },
+        );
+        actions.consume(Action::Build);
+        return;
+    }
+}
+
+/// Merges requested pairs still in their inventory, the crafted item takes the target place.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn craft_requested<IT: Craftable>(
+    mut commands: Commands,
+    mut requests: EventReader<CraftRequest>,
+    recipes: Res<Recipes<IT>>,
+    mut rng: ResMut<crate::RandomDeterministic>,
+    mut crafted: EventWriter<Crafted>,
+    mut q_inventory: Query<&mut Inventory<IT>>,
+    q_item: Query<&IT>,
+    q_rarity: Query<&Rarity>,
+    q_level: Query<&Level>,
+) {
+    for request in requests.read() {
+        let Ok(mut inventory) = q_inventory.get_mut(request.inventory) else {
+            continue;
+        };
+        let position = |item: Entity| inventory.items.iter().position(|i| *i == item);
+        let (Some(source_index), Some(target_index)) =
+            (position(request.source), position(request.target))
+        else {
+            continue;
+        };
+        let (Ok(a), Ok(b)) = (q_item.get(request.source), q_item.get(request.target)) else {
+            continue;
+        };
+        let Some(output) = recipes.find(a, b).copied() else {
+            continue;
+        };
+        let pair = [request.source, request.target];
+        merge(
+            &mut commands,
+            rng.stream(&stream_name::<IT>()),
+            &mut inventory,
+            target_index,
+            source_index,
+            output,
+            best_rarity(&q_rarity, pair),
+            best_level(&q_level, pair),
+        );
+        crafted.send(Crafted {
+            inventory: request.inventory,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buildings::ItemType;
+
+    #[test]
+    fn recipe_file_inputs_in_any_order() {
+        let recipes: Recipes<ItemType> =
+            ron::de::from_str(include_str!("../assets/buildings.recipes.ron")).unwrap();
+        assert_eq!(
+            recipes.find(&ItemType::Gun, &ItemType::Gun),
+            Some(&ItemType::Rifle)
+        );
+        assert_eq!(
+            recipes.find(&ItemType::Aura, &ItemType::Gun),
+            Some(&ItemType::Tesla)
+        );
+        assert_eq!(recipes.find(&ItemType::Rifle, &ItemType::Aura), None);
+    }
+
+    fn crafting_app(app: &mut App, items: Vec<Entity>) -> Entity {
+        app.init_resource::<crate::RandomDeterministic>();
+        app.insert_resource::<Recipes<ItemType>>(
+            ron::de::from_str(include_str!("../assets/buildings.recipes.ron")).unwrap(),
+        );
+        app.add_event::<Crafted>();
+        app.add_event::<CraftRequest>();
+        app.add_systems(
+            Update,
+            (craft_requested::<ItemType>, merge_adjacent::<ItemType>).chain(),
+        );
+        app.world
+            .spawn(Inventory::<ItemType> {
+                items: items.into(),
+                ..default()
+            })
+            .id()
+    }
+
+    fn contents(app: &App, inventory: Entity) -> Vec<(ItemType, Option<Level>)> {
+        app.world
+            .get::<Inventory<ItemType>>(inventory)
+            .unwrap()
+            .items
+            .iter()
+            .map(|item| {
+                let item = app.world.entity(*item);
+                (
+                    *item.get::<ItemType>().unwrap(),
+                    item.get::<Level>().copied(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn locked_and_upgraded_items_only_merge_on_request() {
+        let mut app = App::new();
+        let locked = app.world.spawn((ItemType::Gun, Locked)).id();
+        let plain = app.world.spawn(ItemType::Gun).id();
+        let upgraded = app.world.spawn((ItemType::Gun, Level(3))).id();
+        let items = vec![
+            locked,
+            plain,
+            upgraded,
+            app.world.spawn(ItemType::Gun).id(),
+            app.world.spawn(ItemType::Gun).id(),
+        ];
+        let inventory = crafting_app(&mut app, items);
+        app.update();
+        assert_eq!(
+            contents(&app, inventory),
+            vec![
+                (ItemType::Gun, None),
+                (ItemType::Gun, None),
+                (ItemType::Gun, Some(Level(3))),
+                (ItemType::Rifle, None),
+            ]
+        );
+        assert!(app.world.get::<Locked>(locked).is_some());
+
+        app.world.send_event(CraftRequest {
+            inventory,
+            source: plain,
+            target: upgraded,
+        });
+        app.update();
+        assert_eq!(
+            contents(&app, inventory),
+            vec![
+                (ItemType::Gun, None),
+                (ItemType::Rifle, Some(Level(3))),
+                (ItemType::Rifle, None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use crate::health::{Died, HealthSet};
+use crate::player_command::{Simulation, SimulationSet};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Currency {
+    Gold,
+    Energy,
+}
+
+impl Currency {
+    pub const ALL: [Currency; 2] = [Currency::Gold, Currency::Energy];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Currency::Gold => "Gold",
+            Currency::Energy => "Energy",
+        }
+    }
+}
+
+/// Amounts of several currencies, used for prices and rewards.
+#[derive(Clone, Debug, Default)]
+pub struct Cost(pub Vec<(Currency, u32)>);
+
+impl Cost {
+    /// Every amount multiplied by `ratio`, rounded down.
+    pub fn scaled(&self, ratio: f32) -> Cost {
+        Cost(
+            self.0
+                .iter()
+                .map(|(currency, amount)| (*currency, (*amount as f32 * ratio) as u32))
+                .collect(),
+        )
+    }
+
+    pub fn describe(&self) -> String {
+        self.0
+            .iter()
+            .map(|(currency, amount)| format!("{amount} {}", currency.name()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Implemented by item types which cost something to place.
+pub trait BuildCost {
+    fn build_cost(&self) -> Cost;
+}
+
+/// Currencies owned by the player.
+#[derive(Resource, Debug)]
+pub struct Wallet {
+    pub amounts: HashMap<Currency, u32>,
+}
+
+impl Default for Wallet {
+    fn default() -> Self {
+        Self {
+            amounts: [(Currency::Gold, 50), (Currency::Energy, 10)].into(),
+        }
+    }
+}
+
+impl Wallet {
+    pub fn get(&self, currency: Currency) -> u32 {
+        self.amounts.get(&currency).copied().unwrap_or_default()
+    }
+
+    pub fn can_afford(&self, cost: &Cost) -> bool {
+        cost.0
+            .iter()
+            .all(|(currency, amount)| self.get(*currency) >= *amount)
+    }
+
+    /// Returns false and spends nothing if the cost can't be afforded.
+    pub fn spend(&mut self, cost: &Cost) -> bool {
+        if !self.can_afford(cost) {
+            return false;
+        }
+        for (currency, amount) in cost.0.iter() {
+            *self.amounts.entry(*currency).or_default() -= amount;
+        }
+        true
+    }
+
+    pub fn earn(&mut self, reward: &Cost) {
+        for (currency, amount) in reward.0.iter() {
+            *self.amounts.entry(*currency).or_default() += amount;
+        }
+    }
+}
+
+/// Earned when the entity dies.
+#[derive(Component, Clone, Debug)]
+pub struct Bounty(pub Cost);
+
+/// Earned each time `timer` finishes.
+#[derive(Resource)]
+pub struct PassiveIncome {
+    pub reward: Cost,
+    pub timer: Timer,
+}
+
+impl Default for PassiveIncome {
+    fn default() -> Self {
+        Self {
+            reward: Cost(vec![(Currency::Energy, 1)]),
+            timer: Timer::from_seconds(2f32, TimerMode::Repeating),
+        }
+    }
+}
+
+#[derive(Component)]
+struct WalletText;
+
+pub struct CurrencyPlugin;
+
+impl Plugin for CurrencyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Wallet>();
+        app.init_resource::<PassiveIncome>();
+        app.add_systems(Startup, spawn_hud);
+        app.add_systems(
+            Update,
+            (
+                collect_bounties.in_set(HealthSet::Death),
+                passive_income.in_set(SimulationSet),
+            ),
+        );
+        app.add_systems(PostUpdate, update_hud);
+    }
+}
+
+fn collect_bounties(
+    mut died: EventReader<Died>,
+    mut wallet: ResMut<Wallet>,
+    q_bounty: Query<&Bounty>,
+) {
+    for death in died.read() {
+        if let Ok(bounty) = q_bounty.get(death.entity) {
+            wallet.earn(&bounty.0);
+        }
+    }
+}
+
+fn passive_income(
+    time: Res<Time<Simulation>>,
+    mut income: ResMut<PassiveIncome>,
+    mut wallet: ResMut<Wallet>,
+) {
+    let times = income.timer.tick(time.delta()).times_finished_this_tick();
+    for _ in 0..times {
+        wallet.earn(&income.reward);
+    }
+}
+
+fn spawn_hud(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 20f32,
+                color: Color::GOLD,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8f32),
+            left: Val::Px(8f32),
+            ..default()
+        }),
+        WalletText,
+    ));
+}
+
+fn update_hud(wallet: Res<Wallet>, mut q_text: Query<&mut Text, With<WalletText>>) {
+    if !wallet.is_changed() {
+        return;
+    }
+    for mut text in q_text.iter_mut() {
+        text.sections[0].value = Currency::ALL
+            .iter()
+            .map(|currency| format!("{}: {}", currency.name(), wallet.get(*currency)))
+            .collect::<Vec<_>>()
+            .join("   ");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wallet(gold: u32, energy: u32) -> Wallet {
+        Wallet {
+            amounts: [(Currency::Gold, gold), (Currency::Energy, energy)].into(),
+        }
+    }
+
+    #[test]
+    fn spend_takes_every_currency() {
+        let mut wallet = wallet(30, 10);
+        assert!(wallet.spend(&Cost(vec![(Currency::Gold, 20), (Currency::Energy, 10)])));
+        assert_eq!(wallet.get(Currency::Gold), 10);
+        assert_eq!(wallet.get(Currency::Energy), 0);
+    }
+
+    #[test]
+    fn spend_nothing_when_one_currency_is_missing() {
+        let mut wallet = wallet(30, 4);
+        let cost = Cost(vec![(Currency::Gold, 20), (Currency::Energy, 5)]);
+        assert!(!wallet.can_afford(&cost));
+        assert!(!wallet.spend(&cost));
+        assert_eq!(wallet.get(Currency::Gold), 30);
+        assert_eq!(wallet.get(Currency::Energy), 4);
+    }
+
+    #[test]
+    fn missing_currency_counts_as_zero() {
+        let mut wallet = Wallet { amounts: default() };
+        assert!(wallet.can_afford(&Cost::default()));
+        assert!(!wallet.can_afford(&Cost(vec![(Currency::Gold, 1)])));
+        wallet.earn(&Cost(vec![(Currency::Gold, 3)]));
+        assert_eq!(wallet.get(Currency::Gold), 3);
+        assert_eq!(wallet.get(Currency::Energy), 0);
+    }
+
+    #[test]
+    fn scaled_rounds_down() {
+        let cost = Cost(vec![(Currency::Gold, 15), (Currency::Energy, 1)]).scaled(0.5f32);
+        assert_eq!(cost.0, vec![(Currency::Gold, 7), (Currency::Energy, 0)]);
+    }
+}
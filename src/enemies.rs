@@ -1,114 +1,97 @@
+pub mod behavior;
 pub mod interaction;
+pub mod waves;
 
-use super::HIGHLIGHT_TINT;
 use super::ITEM_VISUAL_SIZE;
+use crate::buildings::interaction::NoBuildArea;
+use crate::camera::ScreenAnchored;
 use crate::inventory_generic::*;
+use crate::item_visual::ItemDef;
+use crate::tooltip::{ItemInfo, ItemMetadata, ItemTooltipPlugin};
 use bevy::ecs::system::EntityCommand;
 use bevy::math::vec3;
 use bevy::prelude::*;
-use bevy::sprite::MaterialMesh2dBundle;
 use bevy::sprite::Mesh2dHandle;
 use bevy::utils::HashMap;
-use bevy_mod_picking::PickableBundle;
+use serde::Deserialize;
+
+const ICONS_PATH: &str = "icons/enemies.png";
 
 pub struct Plugin;
 
 impl bevy::prelude::Plugin for Plugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(crate::inventory_generic::InventoryPlugin::<ItemType>::default());
+        app.add_plugins(ItemTooltipPlugin::<ItemType>::default());
+        app.add_plugins(behavior::Plugin);
+        app.add_plugins(waves::Plugin);
         app.add_systems(Startup, (create_assets, spawn_layout).chain());
     }
 }
 
-#[derive(Resource)]
-pub struct ItemDef {
-    pub mesh: Mesh2dHandle,
-    pub material: Handle<ColorMaterial>,
-}
-
-impl ItemDef {
-    pub(crate) fn create_item_visual(
-        &self,
-    ) -> (
-        MaterialMesh2dBundle<ColorMaterial>,
-        bevy_mod_picking::prelude::Highlight<ColorMaterial>,
-        PickableBundle,
-    ) {
-        (
-            MaterialMesh2dBundle {
-                mesh: self.mesh.clone(),
-                transform: Transform::default().with_scale(Vec3::splat(ITEM_VISUAL_SIZE)),
-                material: self.material.clone(),
-                ..default()
-            },
-            HIGHLIGHT_TINT,
-            PickableBundle::default(), // <- Makes the mesh pickable.
-        )
-    }
-}
-
 #[derive(Resource)]
 pub struct VisualAssets {
     pub item_def: HashMap<ItemType, ItemDef>,
 }
 
+/// Enemies are drawn from the `icons/enemies.png` atlas, one 32 pixels cell per type.
 pub(crate) fn create_assets(
     mut commands: Commands,
+    asset_server: Res<AssetServer>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    let quad: Mesh2dHandle = meshes.add(Mesh::from(shape::Circle::default())).into();
+    let atlas = TextureAtlas::from_grid(
+        asset_server.load(ICONS_PATH),
+        Vec2::splat(32f32),
+        3,
+        1,
+        None,
+        None,
+    );
+    let circle: Mesh2dHandle = meshes.add(Mesh::from(shape::Circle::default())).into();
+    let mut item_def = |item_type: ItemType, index: usize, color: Color| {
+        let def =
+            ItemDef::from_atlas(&mut meshes, &mut materials, &atlas, index).unwrap_or_else(|| {
+                warn!("no cell {index} in {ICONS_PATH}, drawing a placeholder");
+                ItemDef::from_color(circle.clone(), &mut materials, color)
+            });
+        (item_type, def)
+    };
     commands.insert_resource(VisualAssets {
         item_def: [
-            (
-                ItemType::Gun,
-                ItemDef {
-                    mesh: quad.clone(),
-                    material: materials.add(ColorMaterial::from(Color::RED)),
-                },
-            ),
-            (
-                ItemType::Rifle,
-                ItemDef {
-                    mesh: quad.clone(),
-                    material: materials.add(ColorMaterial::from(Color::YELLOW)),
-                },
-            ),
-            (
-                ItemType::Aura,
-                ItemDef {
-                    mesh: quad.clone(),
-                    material: materials.add(ColorMaterial::from(Color::PURPLE)),
-                },
-            ),
+            item_def(ItemType::Gun, 0, Color::RED),
+            item_def(ItemType::Rifle, 1, Color::YELLOW),
+            item_def(ItemType::Aura, 2, Color::PURPLE),
         ]
         .into(),
     });
 }
 
+/// The inventory starts empty, it is fed by [`waves`].
 pub(crate) fn spawn_layout(mut commands: Commands) {
-    let inventory = vec![
-        commands.spawn(ItemType::Gun).id(),
-        commands.spawn(ItemType::Rifle).id(),
-        commands.spawn(ItemType::Aura).id(),
-    ]
-    .into();
-    commands.spawn((
-        Inventory::<ItemType> {
-            items: inventory,
-            ..default()
-        },
+    let slots = vec![
+        vec3(100f32, 0f32, 0f32),
+        vec3(100f32, ITEM_VISUAL_SIZE + 10f32, 0f32),
+        vec3(100f32, (ITEM_VISUAL_SIZE + 10f32) * 2f32, 0f32),
+    ];
+    let area = NoBuildArea::from_slots(&slots);
+    let mut layout = commands.spawn((
+        Inventory::<ItemType>::default(),
         InventoryVisualDef {
-            positions: vec![
-                vec3(100f32, 0f32, 0f32),
-                vec3(100f32, ITEM_VISUAL_SIZE + 10f32, 0f32),
-                vec3(100f32, (ITEM_VISUAL_SIZE + 10f32) * 2f32, 0f32),
-            ],
+            positions: slots.clone(),
+        },
+        ScreenAnchored {
+            camera: None,
+            offsets: slots,
         },
     ));
+    if let Some(area) = area {
+        layout.insert(area);
+    }
 }
 
-#[derive(Component, Clone, Copy, Hash, Eq, PartialEq)]
+#[derive(Component, Clone, Copy, Hash, Eq, PartialEq, Deserialize)]
 pub enum ItemType {
     Gun,
     Rifle,
@@ -122,6 +105,21 @@ impl CommandVisualBuilder for ItemType {
     }
 }
 
+impl ItemMetadata for ItemType {
+    fn item_info(&self) -> ItemInfo {
+        let (name, description) = match self {
+            ItemType::Gun => ("Gunner", "Weak and common."),
+            ItemType::Rifle => ("Sniper", "Tougher, walks slowly."),
+            ItemType::Aura => ("Shaman", "Fast and fragile."),
+        };
+        ItemInfo {
+            name: name.to_string(),
+            description: description.to_string(),
+            ..default()
+        }
+    }
+}
+
 pub struct CreateItemDefVisual {
     pub item_type: ItemType,
 }
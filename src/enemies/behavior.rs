@@ -0,0 +1,197 @@
+use super::ItemType;
+use crate::buildings::behavior::MarkerTarget;
+use crate::currency::{Bounty, Cost, Currency};
+use crate::health::{DamageEvent, Health, HealthSet};
+use crate::inventory_generic::{Inventory, MarkerPlaced};
+use crate::loot::{DropLoot, LootTable};
+use crate::player_command::{Simulation, SimulationSet};
+use crate::ITEM_VISUAL_SIZE;
+use bevy::math::vec2;
+use bevy::prelude::*;
+
+pub struct Plugin;
+
+impl bevy::prelude::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EnemyPath>();
+        app.add_event::<EnemyReachedEnd>();
+        app.add_systems(
+            Update,
+            (
+                spawn_enemies,
+                apply_deferred,
+                follow_path,
+                damage_buildings_on_contact.before(HealthSet::Damage),
+            )
+                .chain()
+                .in_set(SimulationSet),
+        );
+        app.add_systems(Update, draw_path);
+    }
+}
+
+/// Waypoints followed by every enemy, from spawn to the end of the path.
+#[derive(Resource)]
+pub struct EnemyPath {
+    pub waypoints: Vec<Vec2>,
+}
+
+impl Default for EnemyPath {
+    fn default() -> Self {
+        Self {
+            waypoints: vec![
+                vec2(-380f32, 260f32),
+                vec2(-200f32, 260f32),
+                vec2(-200f32, -100f32),
+                vec2(250f32, -100f32),
+                vec2(250f32, 200f32),
+                vec2(380f32, 200f32),
+            ],
+        }
+    }
+}
+
+/// Damage per second dealt to buildings an enemy walks over.
+const CONTACT_DAMAGE: f32 = 20f32;
+
+/// Live enemy, added once an enemy item is placed.
+#[derive(Component)]
+pub struct Enemy {
+    /// World units per second.
+    pub speed: f32,
+}
+
+impl Enemy {
+    /// Components of a live enemy of type `item_type`.
+    pub fn bundle(item_type: ItemType) -> (Self, Health) {
+        let (health, speed) = match item_type {
+            ItemType::Gun => (30f32, 80f32),
+            ItemType::Rifle => (80f32, 50f32),
+            ItemType::Aura => (20f32, 130f32),
+        };
+        (Self { speed }, Health::new(health))
+    }
+
+    /// Gold earned when killing an enemy of type `item_type`.
+    pub fn bounty(item_type: ItemType) -> Bounty {
+        let gold = match item_type {
+            ItemType::Gun => 5,
+            ItemType::Rifle => 12,
+            ItemType::Aura => 8,
+        };
+        Bounty(Cost(vec![(Currency::Gold, gold)]))
+    }
+
+    /// What the player may get when killing an enemy of type `item_type`.
+    pub fn loot_table(item_type: ItemType) -> LootTable<crate::buildings::ItemType> {
+        use crate::buildings::ItemType as Building;
+        let entries = match item_type {
+            ItemType::Gun => vec![(None, 4), (Some(Building::Gun), 1)],
+            ItemType::Rifle => vec![(None, 1), (Some(Building::Rifle), 1)],
+            ItemType::Aura => vec![(None, 2), (Some(Building::Aura), 1)],
+        };
+        LootTable { entries }
+    }
+}
+
+#[derive(Component, Default)]
+pub struct PathFollower {
+    pub next_waypoint: usize,
+}
+
+/// Sent when an enemy walked the whole path, right before it is despawned.
+#[derive(Event)]
+pub struct EnemyReachedEnd {
+    pub enemy: Entity,
+    pub item_type: ItemType,
+}
+
+fn spawn_enemies(
+    mut commands: Commands,
+    path: Res<EnemyPath>,
+    mut q_placed: Query<(Entity, &ItemType, &mut Transform), Added<MarkerPlaced>>,
+    q_building_inventory: Query<Entity, With<Inventory<crate::buildings::ItemType>>>,
+) {
+    for (entity, item_type, mut transform) in q_placed.iter_mut() {
+        if let Some(start) = path.waypoints.first() {
+            transform.translation = start.extend(transform.translation.z);
+        }
+        commands.entity(entity).insert((
+            Enemy::bundle(*item_type),
+            PathFollower::default(),
+            MarkerTarget,
+            Enemy::bounty(*item_type),
+        ));
+        if let Ok(inventory) = q_building_inventory.get_single() {
+            commands.entity(entity).insert(DropLoot {
+                inventory,
+                table: Enemy::loot_table(*item_type),
+            });
+        }
+    }
+}
+
+fn follow_path(
+    mut commands: Commands,
+    time: Res<Time<Simulation>>,
+    path: Res<EnemyPath>,
+    mut reached_end: EventWriter<EnemyReachedEnd>,
+    mut q_enemies: Query<(Entity, &ItemType, &Enemy, &mut PathFollower, &mut Transform)>,
+) {
+    for (entity, item_type, enemy, mut follower, mut transform) in q_enemies.iter_mut() {
+        let mut step = enemy.speed * time.delta_seconds();
+        while let Some(waypoint) = path.waypoints.get(follower.next_waypoint) {
+            let to_waypoint = *waypoint - transform.translation.truncate();
+            if to_waypoint.length() > step {
+                transform.translation += (to_waypoint.normalize() * step).extend(0f32);
+                break;
+            }
+            step -= to_waypoint.length();
+            transform.translation = waypoint.extend(transform.translation.z);
+            follower.next_waypoint += 1;
+        }
+        if follower.next_waypoint >= path.waypoints.len() {
+            reached_end.send(EnemyReachedEnd {
+                enemy: entity,
+                item_type: *item_type,
+            });
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn damage_buildings_on_contact(
+    time: Res<Time<Simulation>>,
+    mut damages: EventWriter<DamageEvent>,
+    q_enemies: Query<&Transform, With<Enemy>>,
+    q_buildings: Query<
+        (Entity, &Transform),
+        (
+            With<crate::buildings::ItemType>,
+            With<MarkerPlaced>,
+            With<Health>,
+        ),
+    >,
+) {
+    for enemy in q_enemies.iter() {
+        for (building, transform) in q_buildings.iter() {
+            if enemy
+                .translation
+                .truncate()
+                .distance(transform.translation.truncate())
+                > ITEM_VISUAL_SIZE
+            {
+                continue;
+            }
+            damages.send(DamageEvent {
+                target: building,
+                amount: CONTACT_DAMAGE * time.delta_seconds(),
+            });
+        }
+    }
+}
+
+fn draw_path(mut gizmos: Gizmos, path: Res<EnemyPath>) {
+    gizmos.linestrip_2d(path.waypoints.iter().copied(), Color::DARK_GRAY);
+}
@@ -1,43 +1,36 @@
-use crate::simple_mouse::MouseWorldPosition;
-
+use crate::actions::{Action, ActionState, InteractionSet};
+use crate::player_command::{CommandKind, CommandQueue, LocalPlayer, PlayerId, Tick};
 use crate::{inventory_generic, Selection};
 use bevy::prelude::*;
-use rand::seq::SliceRandom;
 
 pub struct DebugPlugin;
 
 impl bevy::app::Plugin for DebugPlugin {
     fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (clear_build_requests, click_get_out)
+                .chain()
+                .in_set(InteractionSet::World),
+        );
+        // Requests are spawned by `player_command::execute_commands`.
         app.add_systems(
             Update,
             (
-                clear_build_requests,
-                click_get_out,
                 apply_deferred,
-                (
-                    verify_empty_space,
-                    // TODO: add more checks
-                    apply_deferred,
-                    react_to_build,
-                )
-                    .run_if(component_exist::<BuildRequest>)
-                    .chain(),
+                // TODO: add checks
+                react_to_build.run_if(component_exist::<BuildRequest>),
             )
-                .chain(),
+                .chain()
+                .in_set(InteractionSet::Resolve),
         );
     }
 }
 
 #[derive(Component)]
-struct BuildRequest {
+pub(crate) struct BuildRequest {
     pub inventory: Entity,
     pub item: Entity,
-    pub position: Vec2,
-}
-
-#[derive(Component)]
-enum RefusedBuild {
-    NotEnoughPlace,
 }
 
 fn component_exist<T: Component>(q: Query<Entity, With<T>>) -> bool {
@@ -45,36 +38,32 @@ fn component_exist<T: Component>(q: Query<Entity, With<T>>) -> bool {
 }
 
 fn click_get_out(
-    mut commands: Commands,
-    selection: Query<&Selection>,
-    mut q_inventory: Query<(Entity, &mut inventory_generic::Inventory<super::ItemType>)>,
-    mouse_button_input: Res<Input<MouseButton>>,
-    mouse_position_world: Res<MouseWorldPosition>,
+    selection: Query<(&PlayerId, &Selection), With<LocalPlayer>>,
+    q_inventory: Query<&inventory_generic::Inventory<super::ItemType>>,
+    actions: Res<ActionState>,
+    tick: Res<Tick>,
+    mut queue: ResMut<CommandQueue>,
 ) {
-    if mouse_button_input.just_released(MouseButton::Left) {
-        let selection = selection.single();
-        for mut i in q_inventory.iter_mut() {
-            if selection.inventories[selection.selected_index] != i.0 {
-                continue;
-            }
-            let first = i.1.items.front().unwrap();
-
-            commands.spawn(BuildRequest {
-                inventory: i.0,
-                item: *first,
-                position: mouse_position_world.0,
-            });
-        }
-    }
-}
-
-fn verify_empty_space(mut commands: Commands, q_requests: Query<(Entity, &BuildRequest)>) {
-    for br in q_requests.iter() {
-        info!("build at: {}", &br.1.position.x);
-        if (0f32..100f32).contains(&br.1.position.x) {
-            info!("forbidden");
-            commands.entity(br.0).insert(RefusedBuild::NotEnoughPlace);
+    if actions.just_released(Action::Build) {
+        let Ok((player, selection)) = selection.get_single() else {
+            return;
+        };
+        let Ok(inventory) = q_inventory.get(selection.inventories[selection.selected_index]) else {
+            return;
+        };
+        if inventory.items.is_empty() {
+            return;
         }
+        queue.submit(
+            *player,
+            &tick,
+            CommandKind::Build {
+                inventory: selection.selected_index,
+                item: 0,
+                position: Vec2::ZERO,
+                rotation: Quat::IDENTITY,
+            },
+        );
     }
 }
 
@@ -82,29 +71,22 @@ fn verify_empty_space(mut commands: Commands, q_requests: Query<(Entity, &BuildR
 fn react_to_build(
     mut commands: Commands,
     mut q_inventory: Query<&mut inventory_generic::Inventory<super::ItemType>>,
-    build_events: Query<&BuildRequest, Without<RefusedBuild>>,
-    mut q_transform: Query<&mut Transform>,
-    mut rng: ResMut<crate::RandomDeterministic>,
+    build_events: Query<&BuildRequest>,
 ) {
     for event in build_events.iter() {
-        let mut inventory = q_inventory.get_mut(event.inventory).unwrap();
-        let item_index = inventory
-            .items
-            .iter()
-            .position(|i| *i == event.item)
-            .unwrap();
+        let Ok(mut inventory) = q_inventory.get_mut(event.inventory) else {
+            continue;
+        };
+        // An earlier request of the same tick may have taken it.
+        let Some(item_index) = inventory.items.iter().position(|i| *i == event.item) else {
+            continue;
+        };
         inventory.items.remove(item_index);
-        q_transform.get_mut(event.item).unwrap().translation = event.position.extend(0f32);
-        let choices = [
-            (super::ItemType::Gun, 2),
-            (super::ItemType::Rifle, 1),
-            (super::ItemType::Aura, 1),
-        ];
-        inventory.items.push_back(
-            commands
-                .spawn(choices.choose_weighted(&mut rng.random, |i| i.1).unwrap().0)
-                .id(),
-        );
+        // Enemies start walking from the beginning of the path, see `behavior::spawn_enemies`.
+        commands
+            .entity(event.item)
+            .insert(inventory_generic::MarkerPlaced);
+        // No refill, the inventory is fed by `waves`.
     }
 }
 
@@ -0,0 +1,210 @@
+use super::ItemType;
+use crate::inventory_generic::{Inventory, InventoryVisualDef, MarkerPlaced};
+use crate::player_command::{Simulation, SimulationSet};
+use crate::ron_asset::RonAssetLoader;
+use bevy::asset::LoadState;
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+const WAVES_PATH: &str = "waves/default.waves.ron";
+
+pub struct Plugin;
+
+impl bevy::prelude::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<WaveDefinitions>();
+        app.register_asset_loader(RonAssetLoader::<WaveDefinitions>::new(&["waves.ron"]));
+        app.add_event::<WaveStarted>();
+        app.add_event::<WaveEnded>();
+        app.add_systems(Startup, load_waves);
+        app.add_systems(Update, run_waves.in_set(SimulationSet));
+    }
+}
+
+/// Every wave of a level, played in order.
+#[derive(Asset, TypePath, Deserialize, Clone)]
+pub struct WaveDefinitions {
+    pub waves: Vec<WaveDefinition>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct WaveDefinition {
+    /// How many of each enemy, their order is shuffled when the wave starts.
+    pub composition: Vec<(ItemType, u32)>,
+    /// Seconds between two enemies leaving the queue.
+    pub spawn_interval: f32,
+    /// Seconds to wait before this wave starts.
+    pub delay: f32,
+}
+
+/// The wave file as it was when the game was built, used when it can't be loaded.
+impl Default for WaveDefinitions {
+    fn default() -> Self {
+        ron::de::from_str(include_str!("../../assets/waves/default.waves.ron"))
+            .expect("the built in wave file is valid")
+    }
+}
+
+#[derive(Event)]
+pub struct WaveStarted {
+    pub index: usize,
+}
+
+/// Sent once every enemy of the wave left the queue and is dead or reached the end.
+#[derive(Event)]
+pub struct WaveEnded {
+    pub index: usize,
+}
+
+enum WaveState {
+    /// Waiting for the definitions to load, for `elapsed` seconds. Loading takes more or less
+    /// time on each machine, the first countdown still ends at the same time.
+    Loading {
+        elapsed: f32,
+    },
+    /// Counting down before wave `index` starts.
+    Waiting {
+        index: usize,
+        remaining: f32,
+    },
+    Running {
+        index: usize,
+        /// Enemies not yet pushed into the inventory.
+        pending: VecDeque<ItemType>,
+        until_next_spawn: f32,
+    },
+    Finished,
+}
+
+#[derive(Resource)]
+pub struct WaveScheduler {
+    handle: Handle<WaveDefinitions>,
+    definitions: Option<WaveDefinitions>,
+    state: WaveState,
+}
+
+fn load_waves(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(WaveScheduler {
+        handle: asset_server.load(WAVES_PATH),
+        definitions: None,
+        state: WaveState::Loading { elapsed: 0f32 },
+    });
+}
+
+/// Feeds the enemy inventory over time: each spawn interval, the front enemy leaves the queue
+/// to walk the path, and the next one of the wave is pushed at the back.
+#[allow(clippy::too_many_arguments)]
+fn run_waves(
+    mut commands: Commands,
+    time: Res<Time<Simulation>>,
+    asset_server: Res<AssetServer>,
+    assets: Res<Assets<WaveDefinitions>>,
+    mut scheduler: ResMut<WaveScheduler>,
+    mut rng: ResMut<crate::RandomDeterministic>,
+    mut q_inventory: Query<(&mut Inventory<ItemType>, &InventoryVisualDef)>,
+    q_enemies: Query<(), (With<ItemType>, With<MarkerPlaced>)>,
+    mut started: EventWriter<WaveStarted>,
+    mut ended: EventWriter<WaveEnded>,
+) {
+    let scheduler = &mut *scheduler;
+    let Ok((mut inventory, visual_def)) = q_inventory.get_single_mut() else {
+        return;
+    };
+    let delta = time.delta_seconds();
+    match &mut scheduler.state {
+        WaveState::Loading { elapsed } => {
+            *elapsed += delta;
+            let definitions = if let Some(definitions) = assets.get(&scheduler.handle) {
+                definitions.clone()
+            } else if asset_server.get_load_state(&scheduler.handle) == Some(LoadState::Failed) {
+                warn!("could not load {WAVES_PATH}, using default waves");
+                WaveDefinitions::default()
+            } else {
+                return;
+            };
+            scheduler.state = match definitions.waves.first() {
+                Some(wave) => WaveState::Waiting {
+                    index: 0,
+                    remaining: wave.delay - *elapsed,
+                },
+                None => WaveState::Finished,
+            };
+            scheduler.definitions = Some(definitions);
+        }
+        WaveState::Waiting { index, remaining } => {
+            *remaining -= delta;
+            if *remaining > 0f32 {
+                return;
+            }
+            let index = *index;
+            let wave = scheduler.definitions.as_ref().unwrap().waves[index].clone();
+            let mut enemies: Vec<ItemType> = wave
+                .composition
+                .iter()
+                .flat_map(|(item_type, count)| std::iter::repeat_n(*item_type, *count as usize))
+                .collect();
+            enemies.shuffle(rng.stream("enemies/waves"));
+            let mut pending: VecDeque<ItemType> = enemies.into();
+            // Fill the visible slots so the player sees what is coming.
+            while inventory.items.len() < visual_def.positions.len() {
+                let Some(item_type) = pending.pop_front() else {
+                    break;
+                };
+                inventory.items.push_back(commands.spawn(item_type).id());
+            }
+            info!("wave {} started", index + 1);
+            started.send(WaveStarted { index });
+            scheduler.state = WaveState::Running {
+                index,
+                pending,
+                until_next_spawn: wave.spawn_interval,
+            };
+        }
+        WaveState::Running {
+            index,
+            pending,
+            until_next_spawn,
+        } => {
+            *until_next_spawn -= delta;
+            // Released enemies are only marked at the end of the frame, so check for the end
+            // of the wave on frames without releases.
+            if *until_next_spawn <= 0f32 {
+                let wave = &scheduler.definitions.as_ref().unwrap().waves[*index];
+                *until_next_spawn = wave.spawn_interval;
+                if let Some(front) = inventory.items.pop_front() {
+                    commands.entity(front).insert(MarkerPlaced);
+                }
+                if let Some(item_type) = pending.pop_front() {
+                    inventory.items.push_back(commands.spawn(item_type).id());
+                }
+                return;
+            }
+            if !pending.is_empty() || !inventory.items.is_empty() || !q_enemies.is_empty() {
+                return;
+            }
+            let index = *index;
+            info!("wave {} ended", index + 1);
+            ended.send(WaveEnded { index });
+            scheduler.state = match scheduler.definitions.as_ref().unwrap().waves.get(index + 1) {
+                Some(wave) => WaveState::Waiting {
+                    index: index + 1,
+                    remaining: wave.delay,
+                },
+                None => WaveState::Finished,
+            };
+        }
+        WaveState::Finished => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_waves_parse() {
+        assert!(!WaveDefinitions::default().waves.is_empty());
+    }
+}
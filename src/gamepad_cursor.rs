@@ -0,0 +1,114 @@
+use crate::simple_mouse::{map_cursor, my_cursor_system, CameraCursors, MouseWorldPosition};
+use crate::tooltip::TooltipSet;
+use bevy::input::mouse::MouseMotion;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+/// Stick values below this are ignored.
+const STICK_DEAD_ZONE: f32 = 0.15f32;
+
+/// Cursor moved with the left stick of any gamepad, it replaces the mouse cursor in
+/// [`MouseWorldPosition`] until the mouse moves again.
+#[derive(Resource)]
+pub struct VirtualCursor {
+    /// Window position in logical pixels, like [`Window::cursor_position`].
+    pub position: Vec2,
+    pub active: bool,
+    /// Logical pixels per second at full stick tilt.
+    pub speed: f32,
+}
+
+impl Default for VirtualCursor {
+    fn default() -> Self {
+        Self {
+            position: Vec2::ZERO,
+            active: false,
+            speed: 600f32,
+        }
+    }
+}
+
+pub struct GamepadCursorPlugin;
+
+impl Plugin for GamepadCursorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VirtualCursor>();
+        app.add_systems(
+            Update,
+            (
+                // Before hovering is checked, so the virtual cursor hovers items like the mouse.
+                move_virtual_cursor
+                    .after(my_cursor_system)
+                    .before(TooltipSet::Collect),
+                draw_virtual_cursor,
+            )
+                .chain(),
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn move_virtual_cursor(
+    time: Res<Time>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut cursor: ResMut<VirtualCursor>,
+    mut world_position: ResMut<MouseWorldPosition>,
+    mut cursors: ResMut<CameraCursors>,
+    q_window: Query<(Entity, &Window), With<PrimaryWindow>>,
+    q_camera: Query<(Entity, &Camera, &GlobalTransform)>,
+) {
+    if mouse_motion.read().count() > 0 {
+        cursor.active = false;
+    }
+    let Ok((window_entity, window)) = q_window.get_single() else {
+        return;
+    };
+    let stick = gamepads
+        .iter()
+        .map(|gamepad| {
+            let axis = |axis_type| {
+                axes.get(GamepadAxis::new(gamepad, axis_type))
+                    .unwrap_or_default()
+            };
+            Vec2::new(
+                axis(GamepadAxisType::LeftStickX),
+                axis(GamepadAxisType::LeftStickY),
+            )
+        })
+        .find(|stick| stick.length() > STICK_DEAD_ZONE);
+    if let Some(stick) = stick {
+        if !cursor.active {
+            cursor.active = true;
+            let center = Vec2::new(window.width(), window.height()) / 2f32;
+            cursor.position = window.cursor_position().unwrap_or(center);
+        }
+        // Window y goes down, stick y goes up.
+        let delta = Vec2::new(stick.x, -stick.y) * cursor.speed * time.delta_seconds();
+        cursor.position =
+            (cursor.position + delta).clamp(Vec2::ZERO, Vec2::new(window.width(), window.height()));
+    }
+    if !cursor.active {
+        return;
+    }
+    // Converted every frame, so the cursor keeps its place on screen when the camera moves.
+    map_cursor(
+        window_entity,
+        cursor.position,
+        Some(window_entity),
+        &q_camera,
+        &mut cursors,
+        &mut world_position,
+    );
+}
+
+fn draw_virtual_cursor(
+    mut gizmos: Gizmos,
+    cursor: Res<VirtualCursor>,
+    world_position: Res<MouseWorldPosition>,
+) {
+    if cursor.active {
+        gizmos.circle_2d(world_position.0, 8f32, Color::WHITE);
+    }
+}
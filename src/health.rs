@@ -0,0 +1,207 @@
+use crate::player_command::SimulationSet;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_mod_picking::highlight::InitialHighlight;
+
+/// How long an entity stays tinted after being hit.
+const HIT_FLASH_SECONDS: f32 = 0.1f32;
+
+// Same idea as `HIGHLIGHT_TINT`: the flash material is derived from the entity's base material,
+// only the color changes so textures are kept. Above 1.0 to glow with the camera bloom.
+fn hit_flash_tint(matl: &ColorMaterial) -> ColorMaterial {
+    ColorMaterial {
+        color: Color::rgb(4.0, 4.0, 4.0),
+        ..matl.to_owned()
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+}
+
+/// Request to remove `amount` health from `target`.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+}
+
+/// Sent once when an entity health reaches 0, it is despawned at the end of the frame.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Died {
+    pub entity: Entity,
+}
+
+#[derive(Component)]
+struct MarkerDead;
+
+/// Entity currently showing its flash material, `base` is restored when the timer ends.
+#[derive(Component)]
+struct HitFlash {
+    remaining: f32,
+    base: Handle<ColorMaterial>,
+}
+
+#[derive(Resource, Default)]
+struct HitFlashMaterials {
+    by_base: HashMap<AssetId<ColorMaterial>, Handle<ColorMaterial>>,
+}
+
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub enum HealthSet {
+    /// Applies [`DamageEvent`]s and sends [`Died`].
+    Damage,
+    /// Reacts to [`Died`] before the entity is despawned.
+    Death,
+}
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>();
+        app.add_event::<Died>();
+        app.init_resource::<HitFlashMaterials>();
+        app.configure_sets(
+            Update,
+            (HealthSet::Damage, HealthSet::Death)
+                .chain()
+                .in_set(SimulationSet),
+        );
+        app.add_systems(Update, apply_damage.in_set(HealthSet::Damage));
+        app.add_systems(Update, despawn_dead.in_set(HealthSet::Death));
+        app.add_systems(Update, update_hit_flash.after(HealthSet::Damage));
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn apply_damage(
+    mut commands: Commands,
+    mut damages: EventReader<DamageEvent>,
+    mut died: EventWriter<Died>,
+    mut flash_materials: ResMut<HitFlashMaterials>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut q_health: Query<
+        (
+            &mut Health,
+            Option<&Handle<ColorMaterial>>,
+            Option<&InitialHighlight<ColorMaterial>>,
+            Option<&mut HitFlash>,
+        ),
+        Without<MarkerDead>,
+    >,
+) {
+    for damage in damages.read() {
+        let Ok((mut health, material, highlight, flash)) = q_health.get_mut(damage.target) else {
+            continue;
+        };
+        // Several hits can land the same frame, only die once.
+        if health.current <= 0f32 {
+            continue;
+        }
+        health.current -= damage.amount;
+        if health.current <= 0f32 {
+            died.send(Died {
+                entity: damage.target,
+            });
+            commands.entity(damage.target).insert(MarkerDead);
+            continue;
+        }
+        // A hovered item shows its highlight material, the base one is kept by the highlighting.
+        let material = highlight.map(|h| &h.initial).or(material);
+        match (flash, material) {
+            (Some(mut flash), _) => flash.remaining = HIT_FLASH_SECONDS,
+            (None, Some(material)) => {
+                let Some(base) = materials.get(material).cloned() else {
+                    continue;
+                };
+                let flashed = flash_materials
+                    .by_base
+                    .entry(material.id())
+                    .or_insert_with(|| materials.add(hit_flash_tint(&base)))
+                    .clone();
+                commands.entity(damage.target).insert((
+                    flashed,
+                    HitFlash {
+                        remaining: HIT_FLASH_SECONDS,
+                        base: material.clone(),
+                    },
+                ));
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+fn update_hit_flash(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut q_flash: Query<(Entity, &mut HitFlash)>,
+) {
+    for (entity, mut flash) in q_flash.iter_mut() {
+        flash.remaining -= time.delta_seconds();
+        if flash.remaining <= 0f32 {
+            commands
+                .entity(entity)
+                .insert(flash.base.clone())
+                .remove::<HitFlash>();
+        }
+    }
+}
+
+fn despawn_dead(mut commands: Commands, mut died: EventReader<Died>) {
+    for death in died.read() {
+        commands.entity(death.entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn flash_restores_the_base_material_of_hovered_items() {
+        let mut app = App::new();
+        app.init_resource::<Time>();
+        app.init_resource::<Assets<ColorMaterial>>();
+        app.init_resource::<HitFlashMaterials>();
+        app.add_event::<DamageEvent>();
+        app.add_event::<Died>();
+        app.add_systems(Update, (apply_damage, update_hit_flash).chain());
+        let mut materials = app.world.resource_mut::<Assets<ColorMaterial>>();
+        let base = materials.add(ColorMaterial::from(Color::RED));
+        let hovered = materials.add(ColorMaterial::from(Color::GRAY));
+        let target = app
+            .world
+            .spawn((
+                Health::new(10f32),
+                hovered,
+                InitialHighlight {
+                    initial: base.clone(),
+                },
+            ))
+            .id();
+        app.world.send_event(DamageEvent {
+            target,
+            amount: 1f32,
+        });
+        app.update();
+        assert!(app.world.get::<HitFlash>(target).is_some());
+
+        app.world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(HIT_FLASH_SECONDS * 2f32));
+        app.update();
+        assert!(app.world.get::<HitFlash>(target).is_none());
+        assert_eq!(app.world.get::<Handle<ColorMaterial>>(target), Some(&base));
+    }
+}
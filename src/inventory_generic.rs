@@ -1,5 +1,6 @@
 use bevy::ecs::system::EntityCommand;
 use bevy::prelude::*;
+use bevy::transform::TransformSystem;
 use std::collections::VecDeque;
 use std::marker::PhantomData;
 
@@ -17,6 +18,10 @@ impl<IT: Component + CommandVisualBuilder> Default for InventoryPlugin<IT> {
 
 impl<IT: Component + CommandVisualBuilder> Plugin for InventoryPlugin<IT> {
     fn build(&self, app: &mut App) {
+        app.configure_sets(
+            PostUpdate,
+            InventoryLayoutSet.before(TransformSystem::TransformPropagate),
+        );
         app.add_systems(
             PostUpdate,
             (
@@ -24,11 +29,17 @@ impl<IT: Component + CommandVisualBuilder> Plugin for InventoryPlugin<IT> {
                 apply_deferred,
                 item_reposition::<IT>,
             )
-                .chain(),
+                .chain()
+                .in_set(InventoryLayoutSet),
         );
     }
 }
 
+/// Places inventory items from [`InventoryVisualDef`] in `PostUpdate`, systems moving the
+/// slots run before it.
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct InventoryLayoutSet;
+
 pub trait CommandVisualBuilder {
     type C: EntityCommand;
     fn command_to_create_visual(&self) -> Self::C;
@@ -37,6 +48,10 @@ pub trait CommandVisualBuilder {
 #[derive(Component)]
 pub struct MarkerItemVisual;
 
+/// Item which left its inventory to be placed in the world.
+#[derive(Component)]
+pub struct MarkerPlaced;
+
 #[derive(Component)]
 pub struct Inventory<IT: Component + CommandVisualBuilder> {
     /// entities contained here have a MarkerItem component, it handles logic
@@ -54,6 +69,10 @@ impl<IT: Component + CommandVisualBuilder> Default for Inventory<IT> {
     }
 }
 
+/// Maximum number of items an inventory accepts from player actions, unlimited without it.
+#[derive(Component)]
+pub struct Capacity(pub usize);
+
 #[derive(Component)]
 pub struct InventoryVisualDef {
     pub positions: Vec<Vec3>,
@@ -75,22 +94,30 @@ fn item_create_visual<IT: Component + CommandVisualBuilder>(
         }
     }
 }
+#[allow(clippy::type_complexity)]
 fn item_reposition<IT: Component + CommandVisualBuilder>(
-    inventory: Query<(&Inventory<IT>, &InventoryVisualDef), Changed<Inventory<IT>>>,
-    items_with_visual: Query<(Entity, &IT), With<MarkerItemVisual>>,
-    mut q_transform: Query<&mut Transform>,
+    inventory: Query<
+        (&Inventory<IT>, &InventoryVisualDef),
+        Or<(Changed<Inventory<IT>>, Changed<InventoryVisualDef>)>,
+    >,
+    mut items_with_visual: Query<
+        (&mut Transform, &mut Visibility),
+        (With<IT>, With<MarkerItemVisual>),
+    >,
 ) {
     for (inventory, visual_def) in inventory.iter() {
-        for (i, item) in inventory
-            .items
-            .iter()
-            .take(visual_def.positions.len())
-            .enumerate()
-        {
-            let Ok(item) = items_with_visual.get(*item) else {
+        for (i, item) in inventory.items.iter().enumerate() {
+            let Ok((mut transform, mut visibility)) = items_with_visual.get_mut(*item) else {
                 continue;
             };
-            q_transform.get_mut(item.0).unwrap().translation = visual_def.positions[i];
+            // Items past the visible slots (pickups, reordering) keep their visual but are hidden.
+            match visual_def.positions.get(i) {
+                Some(position) => {
+                    transform.translation = *position;
+                    *visibility = Visibility::Inherited;
+                }
+                None => *visibility = Visibility::Hidden,
+            }
         }
     }
 }
@@ -0,0 +1,124 @@
+use super::HIGHLIGHT_TINT;
+use super::ITEM_VISUAL_SIZE;
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+use bevy::sprite::MaterialMesh2dBundle;
+use bevy::sprite::Mesh2dHandle;
+use bevy_mod_picking::PickableBundle;
+
+/// Describes how an item is drawn.
+///
+/// Every kind of visual (flat color, whole image, texture atlas cell) ends up as a mesh
+/// with a [`ColorMaterial`], so [`HIGHLIGHT_TINT`] keeps working on all of them:
+/// the highlight only changes the material color, which tints the texture.
+#[derive(Clone)]
+pub struct ItemDef {
+    pub mesh: Mesh2dHandle,
+    pub material: Handle<ColorMaterial>,
+}
+
+impl ItemDef {
+    /// Placeholder visual: `mesh` filled with a flat `color`.
+    pub fn from_color(
+        mesh: Mesh2dHandle,
+        materials: &mut Assets<ColorMaterial>,
+        color: Color,
+    ) -> Self {
+        Self {
+            mesh,
+            material: materials.add(ColorMaterial::from(color)),
+        }
+    }
+
+    /// Draws a whole image on `quad`, which should be a unit quad.
+    pub fn from_image(
+        quad: Mesh2dHandle,
+        materials: &mut Assets<ColorMaterial>,
+        image: Handle<Image>,
+    ) -> Self {
+        Self {
+            mesh: quad,
+            material: materials.add(ColorMaterial::from(image)),
+        }
+    }
+
+    /// Draws the cell `index` of `atlas`, `None` when it has no such cell.
+    ///
+    /// A dedicated quad is created with its UVs pointing at the cell, keeping the cell aspect
+    /// ratio with its longest side being 1 (so it is still scaled by [`ITEM_VISUAL_SIZE`]).
+    pub fn from_atlas(
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<ColorMaterial>,
+        atlas: &TextureAtlas,
+        index: usize,
+    ) -> Option<Self> {
+        let rect = *atlas.textures.get(index)?;
+        let size = rect.size() / rect.size().max_element();
+        let mut mesh = Mesh::from(shape::Quad::new(size));
+        let min = rect.min / atlas.size;
+        let max = rect.max / atlas.size;
+        // Same vertex order as `shape::Quad`: bottom left, top left, top right, bottom right.
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            VertexAttributeValues::Float32x2(vec![
+                [min.x, max.y],
+                [min.x, min.y],
+                [max.x, min.y],
+                [max.x, max.y],
+            ]),
+        );
+        Some(Self {
+            mesh: meshes.add(mesh).into(),
+            material: materials.add(ColorMaterial::from(atlas.texture.clone())),
+        })
+    }
+
+    pub(crate) fn create_item_visual(
+        &self,
+    ) -> (
+        MaterialMesh2dBundle<ColorMaterial>,
+        bevy_mod_picking::prelude::Highlight<ColorMaterial>,
+        PickableBundle,
+    ) {
+        (
+            MaterialMesh2dBundle {
+                mesh: self.mesh.clone(),
+                transform: Transform::default().with_scale(Vec3::splat(ITEM_VISUAL_SIZE)),
+                material: self.material.clone(),
+                ..default()
+            },
+            HIGHLIGHT_TINT,
+            PickableBundle::default(), // <- Makes the mesh pickable.
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atlas_cell_uvs_and_aspect() {
+        let mut meshes = Assets::<Mesh>::default();
+        let mut materials = Assets::<ColorMaterial>::default();
+        let mut atlas = TextureAtlas::new_empty(Handle::default(), Vec2::new(128f32, 64f32));
+        atlas.add_texture(Rect::new(0f32, 0f32, 64f32, 64f32));
+        let wide = atlas.add_texture(Rect::new(64f32, 32f32, 128f32, 64f32));
+
+        assert!(ItemDef::from_atlas(&mut meshes, &mut materials, &atlas, 2).is_none());
+        let def = ItemDef::from_atlas(&mut meshes, &mut materials, &atlas, wide).unwrap();
+        let mesh = meshes.get(&def.mesh.0).unwrap();
+        let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0)
+        else {
+            panic!("missing uvs");
+        };
+        assert_eq!(uvs, &vec![[0.5, 1.0], [0.5, 0.5], [1.0, 0.5], [1.0, 1.0]]);
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("missing positions");
+        };
+        // Twice as wide as high, the longest side is 1.
+        assert_eq!(positions[2], [0.5, 0.25, 0.0]);
+    }
+}
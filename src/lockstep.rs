@@ -0,0 +1,708 @@
+pub mod transport;
+
+use crate::actions::InteractionSet;
+use crate::inventory_generic::{CommandVisualBuilder, Inventory, MarkerPlaced};
+use crate::loot::{LootDelivery, LootSettings};
+use crate::player_command::{
+    CommandKind, CommandQueue, PlayerCommand, PlayerId, Players, Simulation, SimulationSet, Tick,
+};
+use crate::{buildings, Fnv1a};
+use bevy::app::{AppLabel, PluginsState, SubApp};
+use bevy::audio::AudioPlugin;
+use bevy::gilrs::GilrsPlugin;
+use bevy::log::LogPlugin;
+use bevy::prelude::*;
+use bevy::render::settings::WgpuSettings;
+use bevy::render::RenderPlugin;
+use bevy::utils::HashMap;
+use bevy::window::ExitCondition;
+use bevy::winit::WinitPlugin;
+use serde::{Deserialize, Serialize};
+use std::any::type_name;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::time::Duration;
+use transport::{LoopbackTransport, Transport, UdpTransport};
+
+/// Commands submitted at a tick are executed this many ticks later, giving them time to reach
+/// the other peers.
+const INPUT_DELAY: u64 = 3;
+/// Local checksums kept to compare with late ones from peers.
+const CHECKSUM_HISTORY: u64 = 120;
+/// Simulated time of a tick, the same on every peer whatever their frame rate.
+const TICK_STEP: Duration = Duration::from_nanos(16_666_667);
+
+/// How peers are connected, from `--lockstep <mode>`.
+#[derive(Clone, Copy, Debug)]
+pub enum LockstepMode {
+    /// `loopback`: the other players are simulated by a peer in this process, with its own
+    /// world, talking through a [`LoopbackTransport`].
+    Loopback,
+    /// `udp`: same as `loopback`, over two UDP sockets on `127.0.0.1`.
+    Udp,
+    /// `<bind>,<peer>`: only the local player is here, other players are in the process at
+    /// `peer`.
+    Remote { bind: SocketAddr, peer: SocketAddr },
+}
+
+/// `--lockstep <mode>` on the command line, else the `LOCKSTEP` environment variable, see
+/// [`LockstepMode`].
+pub fn mode_from_args() -> Option<LockstepMode> {
+    let mode = crate::arg_or_env("--lockstep", "LOCKSTEP")?;
+    match mode.as_str() {
+        "loopback" => Some(LockstepMode::Loopback),
+        "udp" => Some(LockstepMode::Udp),
+        addresses => {
+            let (bind, peer) = addresses.split_once(',')?;
+            match (bind.parse(), peer.parse()) {
+                (Ok(bind), Ok(peer)) => Some(LockstepMode::Remote { bind, peer }),
+                _ => {
+                    error!("invalid lockstep addresses: {addresses}");
+                    None
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum Message {
+    /// Everything `player` does at `tick`, sent even when empty so peers know it did nothing.
+    Commands {
+        player: PlayerId,
+        tick: u64,
+        commands: Vec<CommandKind>,
+    },
+    /// State hash after executing `tick`, on the peer hosting `player`.
+    Checksum {
+        player: PlayerId,
+        tick: u64,
+        hash: u64,
+    },
+}
+
+/// A peer hosted in this process: its players and its connection to the others.
+struct Endpoint {
+    players: Vec<PlayerId>,
+    transport: Box<dyn Transport>,
+}
+
+impl Endpoint {
+    fn send(&mut self, message: &Message) {
+        match ron::to_string(message) {
+            Ok(text) => self.transport.send(text.as_bytes()),
+            Err(e) => error!("could not serialize {message:?}: {e}"),
+        }
+    }
+}
+
+/// Commands are only executed once every player sent its commands for the tick, so all peers
+/// execute the same commands in the same order.
+#[derive(Resource)]
+pub struct Lockstep {
+    endpoints: Vec<Endpoint>,
+    /// Every player of the game, on any peer.
+    players: Vec<PlayerId>,
+    /// Next tick local commands are sent for.
+    next_send: u64,
+    /// Local commands waiting for `next_send`.
+    outgoing: Vec<PlayerCommand>,
+    /// Commands of each player, by tick.
+    received: BTreeMap<u64, HashMap<PlayerId, Vec<CommandKind>>>,
+    /// Last tick whose commands were handed to the executor.
+    released: Option<u64>,
+    local_checksums: BTreeMap<u64, u64>,
+    /// Checksums of peers, by tick, until the local one is known.
+    peer_checksums: BTreeMap<u64, Vec<(PlayerId, u64)>>,
+}
+
+impl Lockstep {
+    fn new(endpoints: Vec<Endpoint>, players: Vec<PlayerId>) -> Self {
+        Self {
+            endpoints,
+            players,
+            next_send: INPUT_DELAY,
+            outgoing: default(),
+            received: default(),
+            released: None,
+            local_checksums: default(),
+            peer_checksums: default(),
+        }
+    }
+
+    /// Whether the commands of every player for `tick` arrived.
+    pub fn ready(&self, tick: u64) -> bool {
+        // Nobody could send commands for the first ticks.
+        tick < INPUT_DELAY
+            || self.received.get(&tick).is_some_and(|commands| {
+                self.players
+                    .iter()
+                    .all(|player| commands.contains_key(player))
+            })
+    }
+}
+
+/// Peers found `tick` in different states.
+#[derive(Event, Debug)]
+pub struct DesyncEvent {
+    pub tick: u64,
+    pub local: u64,
+    pub peer: PlayerId,
+    pub remote: u64,
+}
+
+/// Hash of the state after the last executed tick, see [`LockstepHashPlugin`].
+#[derive(Resource, Default)]
+struct StateHash(u64);
+
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub enum LockstepSet {
+    /// Sends local commands and receives the peers ones, before [`InteractionSet::Execute`].
+    Exchange,
+    /// Parts of [`StateHash`] are added, after [`SimulationSet`].
+    Hash,
+    Verify,
+}
+
+pub struct LockstepPlugin {
+    pub mode: LockstepMode,
+    pub local: PlayerId,
+    pub players: u8,
+}
+
+impl Plugin for LockstepPlugin {
+    fn build(&self, app: &mut App) {
+        let others: Vec<PlayerId> = (0..self.players)
+            .map(PlayerId)
+            .filter(|player| *player != self.local)
+            .collect();
+        let (transport, peer): (Box<dyn Transport>, Option<Box<dyn Transport>>) = match self.mode {
+            LockstepMode::Loopback => {
+                let (a, b) = LoopbackTransport::pair();
+                (Box::new(a), Some(Box::new(b)))
+            }
+            LockstepMode::Udp => match UdpTransport::loopback_pair() {
+                Ok((a, b)) => (Box::new(a), Some(Box::new(b))),
+                Err(e) => {
+                    error!("could not open lockstep sockets: {e}");
+                    return;
+                }
+            },
+            LockstepMode::Remote { bind, peer } => match UdpTransport::connect(bind, peer) {
+                Ok(transport) => (Box::new(transport), None),
+                Err(e) => {
+                    error!("could not connect to {peer}: {e}");
+                    return;
+                }
+            },
+        };
+        info!("lockstep {:?} as {:?}", self.mode, self.local);
+        app.insert_resource(Lockstep::new(
+            vec![Endpoint {
+                players: vec![self.local],
+                transport,
+            }],
+            (0..self.players).map(PlayerId).collect(),
+        ));
+        app.add_plugins(LockstepSyncPlugin);
+        if let Some(transport) = peer {
+            let peer = peer_app(others, self.players, transport);
+            app.insert_sub_app(PeerApp, SubApp::new(peer, |_, _| {}));
+        }
+    }
+}
+
+/// The App of the peer hosting the other players in `loopback` and `udp` modes, updated after
+/// the main one.
+#[derive(AppLabel, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+struct PeerApp;
+
+/// Headless game hosting `hosted` as simulated players, with its own world and lockstep
+/// endpoint, ready to be updated.
+fn peer_app(hosted: Vec<PlayerId>, players: u8, transport: Box<dyn Transport>) -> App {
+    let mut app = App::new();
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                close_when_requested: false,
+            })
+            .set(RenderPlugin {
+                render_creation: WgpuSettings {
+                    backends: None,
+                    ..default()
+                }
+                .into(),
+            })
+            .disable::<LogPlugin>()
+            .disable::<WinitPlugin>()
+            .disable::<AudioPlugin>()
+            .disable::<GilrsPlugin>(),
+    );
+    // Before the game, so it doesn't read them from the command line.
+    app.insert_resource(Players {
+        count: players,
+        local: None,
+        simulated: hosted.clone(),
+    });
+    app.insert_resource(Lockstep::new(
+        vec![Endpoint {
+            players: hosted,
+            transport,
+        }],
+        (0..players).map(PlayerId).collect(),
+    ));
+    app.add_plugins(crate::GamePlugin);
+    app.add_plugins(LockstepSyncPlugin);
+    while app.plugins_state() != PluginsState::Ready {
+        bevy::tasks::tick_global_task_pools_on_main_thread();
+    }
+    app.finish();
+    app.cleanup();
+    app
+}
+
+/// Exchanges commands and checksums through the [`Lockstep`] resource, on every peer.
+struct LockstepSyncPlugin;
+
+impl Plugin for LockstepSyncPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::new_with(Simulation {
+            step: Some(TICK_STEP),
+        }));
+        // Pickups fly to inventories anchored to each player's camera, they would arrive at
+        // different ticks.
+        app.insert_resource(LootSettings {
+            delivery: LootDelivery::Direct,
+            ..default()
+        });
+        app.init_resource::<StateHash>();
+        app.add_event::<DesyncEvent>();
+        app.add_plugins(LockstepHashPlugin::<buildings::ItemType>::default());
+        app.add_plugins(LockstepHashPlugin::<crate::enemies::ItemType>::default());
+        app.configure_sets(
+            Update,
+            (
+                LockstepSet::Exchange
+                    .after(InteractionSet::World)
+                    .before(InteractionSet::Execute),
+                (LockstepSet::Hash, LockstepSet::Verify)
+                    .chain()
+                    .after(SimulationSet)
+                    // Only after a tick was executed.
+                    .run_if(resource_changed::<Tick>()),
+            ),
+        );
+        app.configure_sets(Update, InteractionSet::Execute.run_if(tick_ready));
+        app.add_systems(
+            Update,
+            (send_commands, receive_messages, release_commands)
+                .chain()
+                .in_set(LockstepSet::Exchange),
+        );
+        // Placed and despawned items are hashed in the tick that changed them.
+        app.add_systems(
+            Update,
+            apply_deferred
+                .before(LockstepSet::Hash)
+                .after(SimulationSet),
+        );
+        app.add_systems(
+            Update,
+            (send_checksum, verify_checksums)
+                .chain()
+                .in_set(LockstepSet::Verify),
+        );
+        app.add_systems(Update, log_desyncs);
+    }
+}
+
+/// Adds every `Inventory<IT>` to the state hash, item types in order, and every placed `IT`
+/// with its transform.
+pub struct LockstepHashPlugin<IT: Component + CommandVisualBuilder + Hash> {
+    _item_type: PhantomData<IT>,
+}
+
+impl<IT: Component + CommandVisualBuilder + Hash> Default for LockstepHashPlugin<IT> {
+    fn default() -> Self {
+        Self {
+            _item_type: Default::default(),
+        }
+    }
+}
+
+impl<IT: Component + CommandVisualBuilder + Hash> Plugin for LockstepHashPlugin<IT> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (hash_inventories::<IT>, hash_placed::<IT>).in_set(LockstepSet::Hash),
+        );
+    }
+}
+
+/// Commands of the tick are released before it executes, they aren't in `received` anymore.
+fn tick_ready(lockstep: Res<Lockstep>, tick: Res<Tick>) -> bool {
+    tick.0 < INPUT_DELAY || lockstep.released == Some(tick.0)
+}
+
+/// Sends what local players did since the last tick, once per tick.
+fn send_commands(mut lockstep: ResMut<Lockstep>, tick: Res<Tick>, mut queue: ResMut<CommandQueue>) {
+    let lockstep = &mut *lockstep;
+    // Submitted for the current tick, they are delayed to give peers time to receive them.
+    lockstep.outgoing.append(&mut queue.pending);
+    // Already sent while waiting for peers.
+    if lockstep.next_send > tick.0 + INPUT_DELAY {
+        return;
+    }
+    let target = lockstep.next_send;
+    lockstep.next_send += 1;
+    let outgoing = std::mem::take(&mut lockstep.outgoing);
+    for endpoint in lockstep.endpoints.iter_mut() {
+        for player in endpoint.players.clone() {
+            let commands: Vec<CommandKind> = outgoing
+                .iter()
+                .filter(|c| c.player == player)
+                .map(|c| c.kind.clone())
+                .collect();
+            lockstep
+                .received
+                .entry(target)
+                .or_default()
+                .insert(player, commands.clone());
+            endpoint.send(&Message::Commands {
+                player,
+                tick: target,
+                commands,
+            });
+        }
+    }
+}
+
+fn receive_messages(mut lockstep: ResMut<Lockstep>) {
+    let lockstep = &mut *lockstep;
+    for endpoint in lockstep.endpoints.iter_mut() {
+        for bytes in endpoint.transport.receive() {
+            let message: Message = match ron::de::from_bytes(&bytes) {
+                Ok(message) => message,
+                Err(e) => {
+                    warn!("invalid lockstep message: {e}");
+                    continue;
+                }
+            };
+            match message {
+                Message::Commands {
+                    player,
+                    tick,
+                    commands,
+                } => {
+                    lockstep
+                        .received
+                        .entry(tick)
+                        .or_default()
+                        .insert(player, commands);
+                }
+                Message::Checksum { player, tick, hash } => {
+                    lockstep
+                        .peer_checksums
+                        .entry(tick)
+                        .or_default()
+                        .push((player, hash));
+                }
+            }
+        }
+    }
+}
+
+/// Hands the commands of the tick to the executor, once all arrived.
+fn release_commands(
+    mut lockstep: ResMut<Lockstep>,
+    tick: Res<Tick>,
+    mut queue: ResMut<CommandQueue>,
+) {
+    if !lockstep.ready(tick.0) {
+        return;
+    }
+    let Some(commands) = lockstep.received.remove(&tick.0) else {
+        return;
+    };
+    lockstep.released = Some(tick.0);
+    for (player, commands) in commands {
+        queue
+            .pending
+            .extend(commands.into_iter().map(|kind| PlayerCommand {
+                player,
+                tick: tick.0,
+                kind,
+            }));
+    }
+}
+
+/// Each part is hashed alone then summed, so the order systems and entities are visited in
+/// doesn't matter.
+fn hash_inventories<IT: Component + CommandVisualBuilder + Hash>(
+    mut state: ResMut<StateHash>,
+    q_inventory: Query<&Inventory<IT>>,
+    q_item: Query<&IT>,
+) {
+    for inventory in q_inventory.iter() {
+        let mut hasher = Fnv1a::default();
+        type_name::<IT>().hash(&mut hasher);
+        for item in inventory.items.iter() {
+            q_item.get(*item).ok().hash(&mut hasher);
+        }
+        state.0 = state.0.wrapping_add(hasher.finish());
+    }
+}
+
+fn hash_placed<IT: Component + CommandVisualBuilder + Hash>(
+    mut state: ResMut<StateHash>,
+    q_placed: Query<(&IT, &Transform), With<MarkerPlaced>>,
+) {
+    for (item_type, transform) in q_placed.iter() {
+        let mut hasher = Fnv1a::default();
+        type_name::<IT>().hash(&mut hasher);
+        item_type.hash(&mut hasher);
+        for value in transform
+            .translation
+            .to_array()
+            .into_iter()
+            .chain(transform.rotation.to_array())
+        {
+            value.to_bits().hash(&mut hasher);
+        }
+        state.0 = state.0.wrapping_add(hasher.finish());
+    }
+}
+
+fn send_checksum(mut lockstep: ResMut<Lockstep>, tick: Res<Tick>, mut state: ResMut<StateHash>) {
+    let hash = std::mem::take(&mut state.0);
+    // Nothing was executed yet.
+    let Some(executed) = tick.0.checked_sub(1) else {
+        return;
+    };
+    let lockstep = &mut *lockstep;
+    lockstep.local_checksums.insert(executed, hash);
+    for endpoint in lockstep.endpoints.iter_mut() {
+        let Some(player) = endpoint.players.first().copied() else {
+            continue;
+        };
+        endpoint.send(&Message::Checksum {
+            player,
+            tick: executed,
+            hash,
+        });
+    }
+}
+
+fn verify_checksums(
+    mut lockstep: ResMut<Lockstep>,
+    tick: Res<Tick>,
+    mut desyncs: EventWriter<DesyncEvent>,
+) {
+    let lockstep = &mut *lockstep;
+    let known: Vec<u64> = lockstep
+        .peer_checksums
+        .keys()
+        .copied()
+        .filter(|tick| lockstep.local_checksums.contains_key(tick))
+        .collect();
+    for checked in known {
+        let local = lockstep.local_checksums[&checked];
+        for (peer, remote) in lockstep.peer_checksums.remove(&checked).unwrap_or_default() {
+            if remote != local {
+                desyncs.send(DesyncEvent {
+                    tick: checked,
+                    local,
+                    peer,
+                    remote,
+                });
+            }
+        }
+    }
+    let oldest = tick.0.saturating_sub(CHECKSUM_HISTORY);
+    lockstep.local_checksums.retain(|tick, _| *tick >= oldest);
+    lockstep.peer_checksums.retain(|tick, _| *tick >= oldest);
+}
+
+fn log_desyncs(mut desyncs: EventReader<DesyncEvent>) {
+    for desync in desyncs.read() {
+        error!(
+            "desync at tick {}: local state {:x}, {:?} has {:x}",
+            desync.tick, desync.local, desync.peer, desync.remote
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Commands seen by the stand-in executor.
+    #[derive(Resource, Default)]
+    struct Executed(Vec<PlayerCommand>);
+
+    /// Ticks reported by [`DesyncEvent`]s.
+    #[derive(Resource, Default)]
+    struct Desyncs(Vec<u64>);
+
+    fn collect_desyncs(mut events: EventReader<DesyncEvent>, mut desyncs: ResMut<Desyncs>) {
+        desyncs.0.extend(events.read().map(|event| event.tick));
+    }
+
+    fn execute(
+        mut tick: ResMut<Tick>,
+        mut queue: ResMut<CommandQueue>,
+        mut executed: ResMut<Executed>,
+    ) {
+        executed.0.extend(queue.take_due(tick.0));
+        tick.0 += 1;
+    }
+
+    /// One peer of a two players game, hosting `local`, without the game itself.
+    fn peer(transport: LoopbackTransport, local: PlayerId) -> App {
+        let mut app = App::new();
+        app.insert_resource(Lockstep::new(
+            vec![Endpoint {
+                players: vec![local],
+                transport: Box::new(transport),
+            }],
+            vec![PlayerId(0), PlayerId(1)],
+        ));
+        app.init_resource::<Tick>();
+        app.init_resource::<CommandQueue>();
+        app.init_resource::<StateHash>();
+        app.init_resource::<Executed>();
+        app.add_event::<DesyncEvent>();
+        app.configure_sets(
+            Update,
+            (
+                LockstepSet::Exchange,
+                InteractionSet::Execute.run_if(tick_ready),
+                (LockstepSet::Hash, LockstepSet::Verify)
+                    .chain()
+                    .run_if(resource_changed::<Tick>()),
+            )
+                .chain(),
+        );
+        app.add_systems(
+            Update,
+            (send_commands, receive_messages, release_commands)
+                .chain()
+                .in_set(LockstepSet::Exchange),
+        );
+        app.add_systems(Update, execute.in_set(InteractionSet::Execute));
+        app.add_systems(
+            Update,
+            (send_checksum, verify_checksums)
+                .chain()
+                .in_set(LockstepSet::Verify),
+        );
+        app
+    }
+
+    fn peers() -> [App; 2] {
+        let (a, b) = LoopbackTransport::pair();
+        [peer(a, PlayerId(0)), peer(b, PlayerId(1))]
+    }
+
+    /// Two peers running the game, each simulating one of the players.
+    fn game_peers() -> [App; 2] {
+        let (a, b) = LoopbackTransport::pair();
+        [
+            peer_app(vec![PlayerId(0)], 2, Box::new(a)),
+            peer_app(vec![PlayerId(1)], 2, Box::new(b)),
+        ]
+        .map(|mut app| {
+            app.init_resource::<Desyncs>();
+            app.add_systems(Update, collect_desyncs.after(LockstepSet::Verify));
+            app
+        })
+    }
+
+    fn tick(app: &App) -> u64 {
+        app.world.resource::<Tick>().0
+    }
+
+    #[test]
+    fn fnv1a_reference_values() {
+        let hash = |bytes: &[u8]| {
+            let mut hasher = Fnv1a::default();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+        assert_eq!(hash(b""), 0xcbf29ce484222325);
+        assert_eq!(hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(hash(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn waits_for_every_player() {
+        let [mut a, _b] = peers();
+        for _ in 0..10 {
+            a.update();
+        }
+        // Ticks before the input delay need nobody.
+        assert_eq!(tick(&a), INPUT_DELAY);
+        assert!(!a.world.resource::<Lockstep>().ready(INPUT_DELAY));
+    }
+
+    #[test]
+    fn peers_execute_the_same_commands() {
+        let [mut a, mut b] = peers();
+        let select = CommandKind::Select { inventory: 1 };
+        a.world
+            .resource_mut::<CommandQueue>()
+            .submit(PlayerId(0), &Tick(0), select.clone());
+        b.world
+            .resource_mut::<CommandQueue>()
+            .submit(PlayerId(1), &Tick(0), CommandKind::Undo);
+        for _ in 0..10 {
+            a.update();
+            b.update();
+        }
+        assert_eq!(tick(&a), tick(&b));
+        assert!(tick(&a) > INPUT_DELAY);
+        let expected = vec![
+            PlayerCommand {
+                player: PlayerId(0),
+                tick: INPUT_DELAY,
+                kind: select,
+            },
+            PlayerCommand {
+                player: PlayerId(1),
+                tick: INPUT_DELAY,
+                kind: CommandKind::Undo,
+            },
+        ];
+        assert_eq!(a.world.resource::<Executed>().0, expected);
+        assert_eq!(b.world.resource::<Executed>().0, expected);
+    }
+
+    #[test]
+    fn detects_diverging_game() {
+        let [mut a, mut b] = game_peers();
+        for _ in 0..120 {
+            a.update();
+            b.update();
+        }
+        assert!(tick(&a) > INPUT_DELAY);
+        assert!(a.world.resource::<Desyncs>().0.is_empty());
+        assert!(b.world.resource::<Desyncs>().0.is_empty());
+
+        // A building changes on one peer only.
+        let mut q_inventory = b.world.query::<&Inventory<buildings::ItemType>>();
+        let item = q_inventory.single(&b.world).items[0];
+        *b.world.get_mut::<buildings::ItemType>(item).unwrap() = buildings::ItemType::Tesla;
+        let diverged = tick(&b);
+        for _ in 0..10 {
+            a.update();
+            b.update();
+        }
+        let desyncs = &a.world.resource::<Desyncs>().0;
+        assert!(!desyncs.is_empty());
+        assert!(desyncs.iter().all(|&tick| tick >= diverged));
+    }
+}
@@ -0,0 +1,102 @@
+use bevy::prelude::*;
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+
+/// Largest datagram read by [`UdpTransport`].
+const MAX_DATAGRAM: usize = 65_507;
+
+/// Unreliable message channel to the other peer, messages are whole and in order or lost.
+pub trait Transport: Send + Sync {
+    fn send(&mut self, message: &[u8]);
+    /// Messages arrived since the last call, never blocks.
+    fn receive(&mut self) -> Vec<Vec<u8>>;
+}
+
+type Queue = Arc<Mutex<VecDeque<Vec<u8>>>>;
+
+/// Both ends of the connection live in the same process.
+pub struct LoopbackTransport {
+    inbox: Queue,
+    outbox: Queue,
+}
+
+impl LoopbackTransport {
+    pub fn pair() -> (Self, Self) {
+        let a = Queue::default();
+        let b = Queue::default();
+        (
+            Self {
+                inbox: a.clone(),
+                outbox: b.clone(),
+            },
+            Self {
+                inbox: b,
+                outbox: a,
+            },
+        )
+    }
+}
+
+impl Transport for LoopbackTransport {
+    fn send(&mut self, message: &[u8]) {
+        self.outbox.lock().unwrap().push_back(message.to_vec());
+    }
+
+    fn receive(&mut self) -> Vec<Vec<u8>> {
+        self.inbox.lock().unwrap().drain(..).collect()
+    }
+}
+
+/// One datagram per message, without retransmission: fine on loopback, a lost message stalls
+/// the lockstep.
+pub struct UdpTransport {
+    socket: UdpSocket,
+}
+
+impl UdpTransport {
+    pub fn connect(bind: SocketAddr, peer: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(bind)?;
+        socket.connect(peer)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket })
+    }
+
+    /// Two sockets on `127.0.0.1` connected to each other.
+    pub fn loopback_pair() -> io::Result<(Self, Self)> {
+        let a = UdpSocket::bind("127.0.0.1:0")?;
+        let b = UdpSocket::bind("127.0.0.1:0")?;
+        a.connect(b.local_addr()?)?;
+        b.connect(a.local_addr()?)?;
+        a.set_nonblocking(true)?;
+        b.set_nonblocking(true)?;
+        Ok((Self { socket: a }, Self { socket: b }))
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, message: &[u8]) {
+        if let Err(e) = self.socket.send(message) {
+            warn!("could not send to {:?}: {e}", self.socket.peer_addr());
+        }
+    }
+
+    fn receive(&mut self) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+        let mut buffer = vec![0u8; MAX_DATAGRAM];
+        loop {
+            match self.socket.recv(&mut buffer) {
+                Ok(len) => messages.push(buffer[..len].to_vec()),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                // The peer isn't listening yet.
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => break,
+                Err(e) => {
+                    warn!("could not receive: {e}");
+                    break;
+                }
+            }
+        }
+        messages
+    }
+}
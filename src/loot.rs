@@ -0,0 +1,304 @@
+use crate::health::{Died, HealthSet};
+use crate::inventory_generic::{
+    CommandVisualBuilder, Inventory, InventoryVisualDef, MarkerItemVisual,
+};
+use crate::player_command::{Simulation, SimulationSet};
+use crate::rarity::Rarity;
+use crate::stats::{BaseStats, Modifiers};
+use crate::ITEM_VISUAL_SIZE;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::marker::PhantomData;
+
+/// Weighted drops, `None` entries are the chance to drop nothing.
+#[derive(Clone)]
+pub struct LootTable<IT> {
+    pub entries: Vec<(Option<IT>, u32)>,
+}
+
+impl<IT: Clone> LootTable<IT> {
+    pub fn roll(&self, rng: &mut impl Rng) -> Option<IT> {
+        self.entries
+            .choose_weighted(rng, |entry| entry.1)
+            .ok()
+            .and_then(|entry| entry.0.clone())
+    }
+}
+
+/// When dying, rolls `table` and delivers the result to `inventory`.
+#[derive(Component)]
+pub struct DropLoot<IT: Component + CommandVisualBuilder + Clone> {
+    pub inventory: Entity,
+    pub table: LootTable<IT>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LootDelivery {
+    /// Pushed to the back of the inventory right away.
+    Direct,
+    /// Spawned where the entity died, then flies into the inventory slot reserved for it.
+    Pickup,
+}
+
+#[derive(Resource)]
+pub struct LootSettings {
+    pub delivery: LootDelivery,
+    /// World units per second.
+    pub pickup_speed: f32,
+}
+
+impl Default for LootSettings {
+    fn default() -> Self {
+        // `--loot-delivery direct` skips the pickup flight.
+        let delivery = match crate::arg_or_env("--loot-delivery", "LOOT_DELIVERY").as_deref() {
+            Some("direct") => LootDelivery::Direct,
+            _ => LootDelivery::Pickup,
+        };
+        Self {
+            delivery,
+            pickup_speed: 500f32,
+        }
+    }
+}
+
+/// Dropped item on its way to `inventory`, it is not part of `Inventory::items` yet.
+#[derive(Component)]
+pub struct Pickup {
+    pub inventory: Entity,
+    /// Drop order: its slot is after the items and the pickups dropped before it, which join the
+    /// inventory first.
+    pub order: u64,
+}
+
+/// Item dropped as loot, it comes from the simulation rather than from a player.
+#[derive(Component)]
+pub struct Looted;
+
+/// Handles [`DropLoot<IT>`] for one item type, dropped items get a rarity and affixes.
+pub struct LootPlugin<IT: Component + CommandVisualBuilder + BaseStats + Clone> {
+    _item_type: PhantomData<IT>,
+}
+
+impl<IT: Component + CommandVisualBuilder + BaseStats + Clone> Default for LootPlugin<IT> {
+    fn default() -> Self {
+        Self {
+            _item_type: Default::default(),
+        }
+    }
+}
+
+impl<IT: Component + CommandVisualBuilder + BaseStats + Clone> Plugin for LootPlugin<IT> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LootSettings>();
+        app.add_systems(Update, drop_loot::<IT>.in_set(HealthSet::Death));
+        app.add_systems(Update, fly_pickups::<IT>.in_set(SimulationSet));
+    }
+}
+
+fn drop_loot<IT: Component + CommandVisualBuilder + BaseStats + Clone>(
+    mut commands: Commands,
+    mut died: EventReader<Died>,
+    settings: Res<LootSettings>,
+    mut rng: ResMut<crate::RandomDeterministic>,
+    q_loot: Query<(&DropLoot<IT>, &Transform)>,
+    mut q_inventory: Query<&mut Inventory<IT>>,
+    mut dropped: Local<u64>,
+) {
+    let rng = rng.stream(&format!("loot/{}", std::any::type_name::<IT>()));
+    for death in died.read() {
+        let Ok((loot, transform)) = q_loot.get(death.entity) else {
+            continue;
+        };
+        let Some(item) = loot.table.roll(rng) else {
+            continue;
+        };
+        // Same rolls as the queue refills.
+        let rarity = Rarity::roll(rng);
+        let modifiers = Modifiers::roll(&item.base_stats(), Some(rarity), rng);
+        let Ok(mut inventory) = q_inventory.get_mut(loot.inventory) else {
+            continue;
+        };
+        match settings.delivery {
+            LootDelivery::Direct => {
+                inventory
+                    .items
+                    .push_back(commands.spawn((item, rarity, modifiers, Looted)).id());
+            }
+            LootDelivery::Pickup => {
+                *dropped += 1;
+                let visual = item.command_to_create_visual();
+                commands.spawn(item).add(visual).insert((
+                    rarity,
+                    modifiers,
+                    MarkerItemVisual,
+                    Looted,
+                    Pickup {
+                        inventory: loot.inventory,
+                        order: *dropped,
+                    },
+                    // Overrides the visual default position, drawn above the world.
+                    Transform::from_translation(transform.translation.truncate().extend(2f32))
+                        .with_scale(Vec3::splat(ITEM_VISUAL_SIZE)),
+                ));
+            }
+        }
+    }
+}
+
+fn fly_pickups<IT: Component + CommandVisualBuilder>(
+    mut commands: Commands,
+    time: Res<Time<Simulation>>,
+    settings: Res<LootSettings>,
+    mut q_pickups: Query<(Entity, &Pickup, &mut Transform), With<IT>>,
+    mut q_inventory: Query<(&mut Inventory<IT>, &InventoryVisualDef)>,
+) {
+    // Pickups in flight to each inventory, in drop order.
+    let mut in_flight: HashMap<Entity, Vec<u64>> = HashMap::new();
+    for (_, pickup, _) in q_pickups.iter() {
+        in_flight
+            .entry(pickup.inventory)
+            .or_default()
+            .push(pickup.order);
+    }
+    in_flight.values_mut().for_each(|orders| orders.sort());
+    for (entity, pickup, mut transform) in q_pickups.iter_mut() {
+        let Ok((mut inventory, visual_def)) = q_inventory.get_mut(pickup.inventory) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        let before = in_flight[&pickup.inventory]
+            .iter()
+            .position(|order| *order == pickup.order)
+            .unwrap_or_default();
+        // Its reserved slot, or the last one when the visible slots are full.
+        let Some(target) = visual_def
+            .positions
+            .get(inventory.items.len() + before)
+            .or(visual_def.positions.last())
+        else {
+            inventory.items.push_back(entity);
+            commands.entity(entity).remove::<Pickup>();
+            continue;
+        };
+        let to_target = target.truncate() - transform.translation.truncate();
+        let step = settings.pickup_speed * time.delta_seconds();
+        if to_target.length() > step {
+            transform.translation += (to_target.normalize() * step).extend(0f32);
+            continue;
+        }
+        transform.translation = *target;
+        // Waits in its slot for the pickups dropped before it.
+        if before == 0 {
+            inventory.items.push_back(entity);
+            commands.entity(entity).remove::<Pickup>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buildings::ItemType;
+    use std::time::Duration;
+
+    fn loot_app(delivery: LootDelivery) -> (App, Entity) {
+        let mut app = App::new();
+        app.init_resource::<crate::RandomDeterministic>();
+        app.insert_resource(LootSettings {
+            delivery,
+            pickup_speed: 100f32,
+        });
+        app.init_resource::<Time<Simulation>>();
+        app.add_event::<Died>();
+        app.add_systems(
+            Update,
+            (drop_loot::<ItemType>, fly_pickups::<ItemType>).chain(),
+        );
+        let inventory = app
+            .world
+            .spawn((
+                Inventory::<ItemType>::default(),
+                InventoryVisualDef {
+                    positions: (0..3)
+                        .map(|i| Vec3::new(0f32, 10f32 * i as f32, 0f32))
+                        .collect(),
+                },
+            ))
+            .id();
+        (app, inventory)
+    }
+
+    #[test]
+    fn dropped_items_get_rarity_and_affixes() {
+        let (mut app, inventory) = loot_app(LootDelivery::Direct);
+        let enemy = app
+            .world
+            .spawn((
+                DropLoot {
+                    inventory,
+                    table: LootTable {
+                        entries: vec![(Some(ItemType::Gun), 1)],
+                    },
+                },
+                Transform::default(),
+            ))
+            .id();
+        app.world.send_event(Died { entity: enemy });
+        app.update();
+        let items = &app
+            .world
+            .get::<Inventory<ItemType>>(inventory)
+            .unwrap()
+            .items;
+        assert_eq!(items.len(), 1);
+        let item = app.world.entity(items[0]);
+        assert!(item.contains::<Rarity>());
+        assert!(item.contains::<Modifiers>());
+    }
+
+    #[test]
+    fn pickups_fly_to_their_own_slot() {
+        let (mut app, inventory) = loot_app(LootDelivery::Pickup);
+        // The second one dropped is closer, it waits in its slot for the first.
+        let [first, second] = [(1, 25f32), (2, 5f32)].map(|(order, x)| {
+            app.world
+                .spawn((
+                    ItemType::Gun,
+                    Pickup { inventory, order },
+                    Transform::from_xyz(x, 0f32, 0f32),
+                ))
+                .id()
+        });
+        let position =
+            |app: &App, item: Entity| app.world.get::<Transform>(item).unwrap().translation;
+        let step = |app: &mut App| {
+            app.world
+                .resource_mut::<Time<Simulation>>()
+                .advance_by(Duration::from_millis(100));
+            app.update();
+        };
+        step(&mut app);
+        step(&mut app);
+        assert_eq!(position(&app, second), Vec3::new(0f32, 10f32, 0f32));
+        assert!(app
+            .world
+            .get::<Inventory<ItemType>>(inventory)
+            .unwrap()
+            .items
+            .is_empty());
+
+        for _ in 0..3 {
+            step(&mut app);
+        }
+        assert_eq!(position(&app, first), Vec3::ZERO);
+        assert_eq!(
+            app.world
+                .get::<Inventory<ItemType>>(inventory)
+                .unwrap()
+                .items,
+            [first, second]
+        );
+    }
+}
@@ -1,18 +1,38 @@
+mod actions;
 pub mod buildings;
+mod camera;
+mod crafting;
+mod currency;
 pub mod enemies;
+mod gamepad_cursor;
+mod health;
 mod inventory_generic;
+mod item_visual;
+mod lockstep;
+mod loot;
+mod player_command;
+mod rarity;
+mod ron_asset;
 mod simple_mouse;
+mod stats;
+mod tooltip;
 
+use actions::{Action, ActionState};
 use bevy::{
     core_pipeline::bloom::BloomSettings,
     ecs::schedule::{LogLevel, ScheduleBuildSettings},
     math::vec4,
     prelude::*,
+    utils::HashMap,
 };
 use bevy_mod_picking::prelude::*;
+use player_command::{
+    CommandKind, CommandQueue, LocalPlayer, PlayerId, Players, SimulatedPlayer, Tick,
+};
 use rand::prelude::*;
 use rand_chacha::ChaCha20Rng;
 use simple_mouse::MainCamera;
+use std::hash::Hasher;
 
 const ITEM_VISUAL_SIZE: f32 = 64f32;
 const HOVERED: Color = Color::rgb(0.25, 0.25, 0.25);
@@ -60,33 +80,139 @@ fn main() {
         .run();
 }
 
+/// Master seed and the named random streams forked from it.
+///
+/// Each consumer draws from its own stream, so adding a new random consumer or changing how
+/// much one of them rolls doesn't perturb the others.
 #[derive(Resource)]
 pub struct RandomDeterministic {
-    pub random: ChaCha20Rng,
     pub seed: u64,
+    streams: HashMap<String, ChaCha20Rng>,
 }
 impl Default for RandomDeterministic {
     fn default() -> Self {
-        let seed = 0; //thread_rng().gen::<u64>();
+        let seed = seed_from_args().unwrap_or(0);
+        info!("random seed: {seed}");
         Self {
-            random: ChaCha20Rng::seed_from_u64(seed),
             seed,
+            streams: default(),
         }
     }
 }
 
+impl RandomDeterministic {
+    /// New generator for `name`, always the same for a given seed.
+    pub fn fork(&self, name: &str) -> ChaCha20Rng {
+        let mut random = ChaCha20Rng::seed_from_u64(self.seed);
+        random.set_stream(stream_id(name));
+        random
+    }
+
+    /// Shared generator for `name`, forked on first use.
+    pub fn stream(&mut self, name: &str) -> &mut ChaCha20Rng {
+        if !self.streams.contains_key(name) {
+            let random = self.fork(name);
+            self.streams.insert(name.to_string(), random);
+        }
+        self.streams.get_mut(name).unwrap()
+    }
+}
+
+fn stream_id(name: &str) -> u64 {
+    let mut hasher = Fnv1a::default();
+    hasher.write(name.as_bytes());
+    hasher.finish()
+}
+
+/// FNV-1a, stable across builds and platforms unlike the std hasher.
+pub(crate) struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// `--seed <n>` or `--seed=<n>` on the command line, else the `SEED` environment variable.
+fn seed_from_args() -> Option<u64> {
+    arg_or_env("--seed", "SEED")?.parse().ok()
+}
+
+/// Value of `<flag> <value>` or `<flag>=<value>` on the command line, else of the `env`
+/// environment variable.
+pub(crate) fn arg_or_env(flag: &str, env: &str) -> Option<String> {
+    let mut args = std::env::args();
+    let prefix = format!("{flag}=");
+    loop {
+        let Some(arg) = args.next() else {
+            break None;
+        };
+        if arg == flag {
+            break args.next();
+        }
+        if let Some(value) = arg.strip_prefix(&prefix) {
+            break Some(value.to_string());
+        }
+    }
+    .or_else(|| std::env::var(env).ok())
+}
+
 pub struct InventoryPlugin;
 
 impl Plugin for InventoryPlugin {
     fn build(&self, app: &mut App) {
+        app.add_plugins(GamePlugin);
+        app.add_plugins(camera::CameraPlugin);
+        if let Some(mode) = lockstep::mode_from_args() {
+            let players = player_command::player_count_from_args();
+            app.add_plugins(lockstep::LockstepPlugin {
+                mode,
+                local: player_command::local_player_from_args(players),
+                players,
+            });
+        }
+        app.add_systems(Startup, spawn_camera);
+    }
+}
+
+/// The game itself, without a camera so it also runs headless, like the lockstep peers.
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(actions::ActionsPlugin);
         app.add_plugins(buildings::interaction::DebugPlugin);
         app.add_plugins(enemies::interaction::DebugPlugin);
         app.add_plugins(simple_mouse::MousePlugin);
+        app.add_plugins(gamepad_cursor::GamepadCursorPlugin);
+        app.add_plugins(tooltip::TooltipPlugin);
+        app.add_plugins(rarity::RarityPlugin);
+        app.add_plugins(health::HealthPlugin);
+        app.add_plugins(currency::CurrencyPlugin);
         app.add_plugins(buildings::Plugin);
         app.add_plugins(enemies::Plugin);
-        app.add_systems(Startup, spawn_camera);
+        app.configure_sets(
+            Update,
+            actions::InteractionSet::Item.after(tooltip::TooltipSet::Display),
+        );
+        app.add_plugins(player_command::PlayerCommandPlugin);
         app.add_systems(PostStartup, (apply_deferred, setup_selection).chain());
-        app.add_systems(Update, cycle_selection);
+        app.add_systems(
+            Update,
+            cycle_selection.in_set(actions::InteractionSet::World),
+        );
         app.init_resource::<RandomDeterministic>();
     }
 }
@@ -105,33 +231,54 @@ fn spawn_camera(mut commands: Commands) {
     ));
 }
 
+/// Inventories a player can act on, one per player.
 #[derive(Component)]
 pub struct Selection {
     pub inventories: Vec<Entity>,
     pub selected_index: usize,
 }
 
+/// Inventories are listed buildings first, in spawn order, so their indices are the same on
+/// every peer.
 fn setup_selection(
     mut commands: Commands,
-    q_inventories: Query<
-        Entity,
-        Or<(
-            With<inventory_generic::Inventory<buildings::ItemType>>,
-            With<inventory_generic::Inventory<enemies::ItemType>>,
-        )>,
-    >,
+    players: Res<Players>,
+    q_buildings: Query<Entity, With<inventory_generic::Inventory<buildings::ItemType>>>,
+    q_enemies: Query<Entity, With<inventory_generic::Inventory<enemies::ItemType>>>,
 ) {
-    commands.spawn(Selection {
-        inventories: q_inventories.iter().collect(),
-        selected_index: 0,
-    });
+    let inventories: Vec<Entity> = q_buildings.iter().chain(q_enemies.iter()).collect();
+    info!("players: {}, local: {:?}", players.count, players.local);
+    for player in (0..players.count).map(PlayerId) {
+        let mut selection = commands.spawn((
+            Selection {
+                inventories: inventories.clone(),
+                selected_index: 0,
+            },
+            player,
+        ));
+        if players.local == Some(player) {
+            selection.insert(LocalPlayer);
+        } else if players.simulated.contains(&player) {
+            selection.insert(SimulatedPlayer);
+        }
+    }
 }
 
-pub fn cycle_selection(mut q_selection: Query<&mut Selection>, input: Res<Input<KeyCode>>) {
-    if input.just_pressed(KeyCode::C) {
-        let mut s = q_selection.single_mut();
-        s.selected_index += 1;
-        s.selected_index %= s.inventories.len();
-        info!("Selected: {}", s.selected_index);
+pub fn cycle_selection(
+    q_selection: Query<(&PlayerId, &Selection), With<LocalPlayer>>,
+    actions: Res<ActionState>,
+    tick: Res<Tick>,
+    mut queue: ResMut<CommandQueue>,
+) {
+    let Ok((player, s)) = q_selection.get_single() else {
+        return;
+    };
+    if actions.just_pressed(Action::SelectNext) {
+        let inventory = (s.selected_index + 1) % s.inventories.len();
+        queue.submit(*player, &tick, CommandKind::Select { inventory });
+    }
+    if actions.just_pressed(Action::SelectPrevious) {
+        let inventory = (s.selected_index + s.inventories.len() - 1) % s.inventories.len();
+        queue.submit(*player, &tick, CommandKind::Select { inventory });
     }
 }
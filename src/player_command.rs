@@ -0,0 +1,584 @@
+use crate::actions::InteractionSet;
+use crate::buildings::history::HistoryRequest;
+use crate::buildings::pickup::PickUpRequest;
+use crate::buildings::queue::{discard_at, reroll_unlocked, Locked, Upcoming};
+use crate::crafting::CraftRequest;
+use crate::currency::Wallet;
+use crate::inventory_generic::Inventory;
+use crate::rarity::{sort_by_rarity, Rarity};
+use crate::{buildings, enemies, RandomDeterministic, Selection};
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f32::consts::FRAC_PI_2;
+use std::time::Duration;
+
+/// Ticks between two commands of a simulated player.
+const SIMULATED_PERIOD: u64 = 90;
+
+/// Player owning a [`Selection`] and issuing commands.
+#[derive(
+    Component, Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct PlayerId(pub u8);
+
+/// Selection of the player using this machine inputs.
+#[derive(Component)]
+pub struct LocalPlayer;
+
+/// Player whose commands are rolled instead of read from inputs, to exercise several players in
+/// one process.
+#[derive(Component)]
+pub struct SimulatedPlayer;
+
+/// Players of the game and who drives each of them in this world. Players neither local nor
+/// simulated are played on another peer, see [`crate::lockstep`].
+#[derive(Resource, Clone, Debug)]
+pub struct Players {
+    pub count: u8,
+    /// Gets the [`LocalPlayer`] marker.
+    pub local: Option<PlayerId>,
+    /// Get the [`SimulatedPlayer`] marker.
+    pub simulated: Vec<PlayerId>,
+}
+
+/// From the command line, see [`player_count_from_args`] and [`local_player_from_args`].
+impl Default for Players {
+    fn default() -> Self {
+        let count = player_count_from_args();
+        let local = local_player_from_args(count);
+        // With lockstep, the other players are hosted by a peer.
+        let simulated = if crate::lockstep::mode_from_args().is_some() {
+            Vec::new()
+        } else {
+            (0..count)
+                .map(PlayerId)
+                .filter(|player| *player != local)
+                .collect()
+        };
+        Self {
+            count,
+            local: Some(local),
+            simulated,
+        }
+    }
+}
+
+/// Simulation step, incremented each time commands are executed.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tick(pub u64);
+
+/// Clock of the simulation, advanced once per tick: by the frame time, or by `step` when every
+/// machine must advance the same.
+#[derive(Default)]
+pub struct Simulation {
+    pub step: Option<Duration>,
+}
+
+/// Systems changing the world on their own, like enemies, turrets or income. They run once after
+/// each executed tick and read `Time<Simulation>`, so peers waiting for commands stay in step.
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct SimulationSet;
+
+/// Every change a player makes to inventories goes through one of these.
+///
+/// Inventories are designated by their index in the player [`Selection`] and items by their index
+/// in the inventory, so commands stay meaningful on another machine.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CommandKind {
+    Select {
+        inventory: usize,
+    },
+    Build {
+        inventory: usize,
+        item: usize,
+        position: Vec2,
+        rotation: Quat,
+    },
+    Discard {
+        inventory: usize,
+        item: usize,
+    },
+    /// Moves the item at `from` so it ends at `to`, items between shift.
+    MoveItem {
+        inventory: usize,
+        from: usize,
+        to: usize,
+    },
+    /// Rerolls every unlocked item of a building queue.
+    Reroll {
+        inventory: usize,
+    },
+    ToggleLock {
+        inventory: usize,
+        item: usize,
+    },
+    /// Sorts by decreasing rarity.
+    Sort {
+        inventory: usize,
+    },
+    /// Merges the item at `from` into the one at `onto`, following the recipes.
+    Craft {
+        inventory: usize,
+        from: usize,
+        onto: usize,
+    },
+    /// Puts the placed building at `position` back in its inventory.
+    PickUp {
+        position: Vec2,
+    },
+    Sell {
+        position: Vec2,
+    },
+    /// Reverts the last change to the buildings, when the player made it.
+    Undo,
+    Redo,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerCommand {
+    pub player: PlayerId,
+    /// Tick the command is executed at.
+    pub tick: u64,
+    pub kind: CommandKind,
+}
+
+/// Commands waiting for their tick.
+#[derive(Resource, Default)]
+pub struct CommandQueue {
+    pub pending: Vec<PlayerCommand>,
+}
+
+impl CommandQueue {
+    pub fn submit(&mut self, player: PlayerId, tick: &Tick, kind: CommandKind) {
+        self.pending.push(PlayerCommand {
+            player,
+            tick: tick.0,
+            kind,
+        });
+    }
+
+    /// Commands due at `tick`, ordered by player then submission, the same on every machine.
+    pub fn take_due(&mut self, tick: u64) -> Vec<PlayerCommand> {
+        let (mut due, pending) = self.pending.drain(..).partition(|c| c.tick <= tick);
+        self.pending = pending;
+        // Stable, so commands of a player keep their order.
+        due.sort_by_key(|c: &PlayerCommand| (c.tick, c.player));
+        due
+    }
+}
+
+pub struct PlayerCommandPlugin;
+
+impl Plugin for PlayerCommandPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Players>();
+        app.init_resource::<Tick>();
+        app.init_resource::<CommandQueue>();
+        app.add_event::<CraftRequest>();
+        app.add_event::<PickUpRequest>();
+        app.add_event::<HistoryRequest>();
+        // Kept when set up by another plugin, see `lockstep`.
+        if !app.world.contains_resource::<Time<Simulation>>() {
+            app.init_resource::<Time<Simulation>>();
+        }
+        app.configure_sets(
+            Update,
+            SimulationSet
+                .after(InteractionSet::Resolve)
+                .run_if(resource_changed::<Tick>()),
+        );
+        app.add_systems(Update, simulate_players.in_set(InteractionSet::World));
+        app.add_systems(Update, execute_commands.in_set(InteractionSet::Execute));
+    }
+}
+
+/// `--players <n>` or `--players=<n>` on the command line, else the `PLAYERS` environment
+/// variable. Players other than the local one are simulated, unless they are on another machine.
+pub fn player_count_from_args() -> u8 {
+    crate::arg_or_env("--players", "PLAYERS")
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(1)
+        .max(1)
+}
+
+/// `--player <id>` or `--player=<id>` on the command line, else the `PLAYER` environment
+/// variable: the player using this machine inputs, one of the `players`.
+pub fn local_player_from_args(players: u8) -> PlayerId {
+    let id = crate::arg_or_env("--player", "PLAYER")
+        .and_then(|id| id.parse().ok())
+        .unwrap_or(0);
+    checked_local_player(id, players)
+}
+
+/// Ids start at 0, an id past the last player falls back to the first one.
+fn checked_local_player(id: u8, players: u8) -> PlayerId {
+    if id >= players {
+        error!("player {id} doesn't exist with {players} players, playing as player 0");
+        return PlayerId(0);
+    }
+    PlayerId(id)
+}
+
+pub fn move_item(items: &mut VecDeque<Entity>, from: usize, to: usize) {
+    if from >= items.len() || to >= items.len() {
+        return;
+    }
+    if let Some(item) = items.remove(from) {
+        items.insert(to, item);
+    }
+}
+
+/// The only system applying player commands, in tick then player order.
+///
+/// Commands needing more of the world send a request, resolved in [`InteractionSet::Resolve`].
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+pub(crate) fn execute_commands(
+    mut commands: Commands,
+    mut tick: ResMut<Tick>,
+    mut queue: ResMut<CommandQueue>,
+    mut wallet: ResMut<Wallet>,
+    mut q_selection: Query<(&PlayerId, &mut Selection)>,
+    mut q_buildings: Query<(&mut Inventory<buildings::ItemType>, Option<&mut Upcoming>)>,
+    mut q_enemies: Query<&mut Inventory<enemies::ItemType>>,
+    q_locked: Query<(), With<Locked>>,
+    q_rarity: Query<&Rarity>,
+    mut crafts: EventWriter<CraftRequest>,
+    mut pick_ups: EventWriter<PickUpRequest>,
+    mut history: EventWriter<HistoryRequest>,
+    virtual_time: Res<Time<Virtual>>,
+    mut simulation: ResMut<Time<Simulation>>,
+) {
+    // Items already taken by a command of this tick: later ones on the same item are dropped,
+    // when two players build it or one builds and discards it.
+    let mut claimed = HashSet::new();
+    for command in queue.take_due(tick.0) {
+        let Some((_, mut selection)) = q_selection
+            .iter_mut()
+            .find(|(player, _)| **player == command.player)
+        else {
+            continue;
+        };
+        match command.kind {
+            CommandKind::Select { inventory } => {
+                if inventory < selection.inventories.len() {
+                    selection.selected_index = inventory;
+                    info!("{:?} selected: {}", command.player, inventory);
+                }
+            }
+            CommandKind::Build {
+                inventory,
+                item,
+                position,
+                rotation,
+            } => {
+                let Some(inventory) = selection.inventories.get(inventory).copied() else {
+                    continue;
+                };
+                if let Ok((items, _)) = q_buildings.get(inventory) {
+                    let Some(item) = items.items.get(item).filter(|i| claimed.insert(**i)) else {
+                        continue;
+                    };
+                    commands.spawn(buildings::interaction::BuildRequest {
+                        inventory,
+                        item: *item,
+                        position,
+                        rotation,
+                    });
+                } else if let Ok(items) = q_enemies.get(inventory) {
+                    let Some(item) = items.items.get(item).filter(|i| claimed.insert(**i)) else {
+                        continue;
+                    };
+                    commands.spawn(enemies::interaction::BuildRequest {
+                        inventory,
+                        item: *item,
+                    });
+                }
+            }
+            CommandKind::Discard { inventory, item } => {
+                let Some(Ok((mut items, Some(mut upcoming)))) = selection
+                    .inventories
+                    .get(inventory)
+                    .copied()
+                    .map(|e| q_buildings.get_mut(e))
+                else {
+                    continue;
+                };
+                if !items.items.get(item).is_some_and(|i| claimed.insert(*i)) {
+                    continue;
+                }
+                discard_at(&mut commands, &mut items, &mut upcoming, item);
+            }
+            CommandKind::MoveItem {
+                inventory,
+                from,
+                to,
+            } => {
+                let Some(inventory) = selection.inventories.get(inventory).copied() else {
+                    continue;
+                };
+                if let Ok((mut items, _)) = q_buildings.get_mut(inventory) {
+                    move_item(&mut items.items, from, to);
+                } else if let Ok(mut items) = q_enemies.get_mut(inventory) {
+                    move_item(&mut items.items, from, to);
+                }
+            }
+            CommandKind::Reroll { inventory } => {
+                let Some(Ok((mut items, Some(mut upcoming)))) = selection
+                    .inventories
+                    .get(inventory)
+                    .copied()
+                    .map(|e| q_buildings.get_mut(e))
+                else {
+                    continue;
+                };
+                // Claimed items are gone or about to be, they aren't rerolled.
+                let is_kept = |item: Entity| q_locked.contains(item) || claimed.contains(&item);
+                reroll_unlocked(
+                    &mut commands,
+                    &mut wallet,
+                    &mut items,
+                    &mut upcoming,
+                    is_kept,
+                );
+            }
+            CommandKind::ToggleLock { inventory, item } => {
+                let Some(Ok((items, _))) = selection
+                    .inventories
+                    .get(inventory)
+                    .map(|e| q_buildings.get(*e))
+                else {
+                    continue;
+                };
+                let Some(item) = items.items.get(item) else {
+                    continue;
+                };
+                if q_locked.contains(*item) {
+                    commands.entity(*item).remove::<Locked>();
+                } else {
+                    commands.entity(*item).insert(Locked);
+                }
+            }
+            CommandKind::Sort { inventory } => {
+                let Some(inventory) = selection.inventories.get(inventory).copied() else {
+                    continue;
+                };
+                if let Ok((mut items, _)) = q_buildings.get_mut(inventory) {
+                    sort_by_rarity(&mut items.items, &q_rarity);
+                } else if let Ok(mut items) = q_enemies.get_mut(inventory) {
+                    sort_by_rarity(&mut items.items, &q_rarity);
+                }
+            }
+            CommandKind::Craft {
+                inventory,
+                from,
+                onto,
+            } => {
+                let Some(inventory) = selection.inventories.get(inventory).copied() else {
+                    continue;
+                };
+                let items = match (q_buildings.get(inventory), q_enemies.get(inventory)) {
+                    (Ok((items, _)), _) => [items.items.get(from), items.items.get(onto)],
+                    (_, Ok(items)) => [items.items.get(from), items.items.get(onto)],
+                    _ => continue,
+                };
+                let [Some(source), Some(target)] = items.map(|i| i.copied()) else {
+                    continue;
+                };
+                if source == target || claimed.contains(&source) || claimed.contains(&target) {
+                    continue;
+                }
+                claimed.extend([source, target]);
+                crafts.send(CraftRequest {
+                    inventory,
+                    source,
+                    target,
+                });
+            }
+            CommandKind::PickUp { position } => {
+                pick_ups.send(PickUpRequest {
+                    position,
+                    sell: false,
+                });
+            }
+            CommandKind::Sell { position } => {
+                pick_ups.send(PickUpRequest {
+                    position,
+                    sell: true,
+                });
+            }
+            CommandKind::Undo => {
+                history.send(HistoryRequest::Undo(command.player));
+            }
+            CommandKind::Redo => {
+                history.send(HistoryRequest::Redo(command.player));
+            }
+        }
+    }
+    tick.0 += 1;
+    let step = simulation.context().step.unwrap_or(virtual_time.delta());
+    simulation.advance_by(step);
+}
+
+/// Rolls a command every [`SIMULATED_PERIOD`] ticks for each simulated player, from its own
+/// random stream.
+fn simulate_players(
+    tick: Res<Tick>,
+    mut rng: ResMut<RandomDeterministic>,
+    mut queue: ResMut<CommandQueue>,
+    q_selection: Query<(&PlayerId, &Selection), With<SimulatedPlayer>>,
+    q_buildings: Query<&Inventory<buildings::ItemType>>,
+    q_enemies: Query<&Inventory<enemies::ItemType>>,
+) {
+    if tick.0 == 0 || !tick.0.is_multiple_of(SIMULATED_PERIOD) {
+        return;
+    }
+    for (player, selection) in q_selection.iter() {
+        let rng = rng.stream(&format!("players/{}", player.0));
+        let inventory = selection.selected_index;
+        let Some(selected) = selection.inventories.get(inventory) else {
+            continue;
+        };
+        let len = q_buildings
+            .get(*selected)
+            .map(|i| i.items.len())
+            .or(q_enemies.get(*selected).map(|i| i.items.len()))
+            .unwrap_or_default();
+        let kind = match rng.gen_range(0..4) {
+            // Nothing to act on in an empty inventory.
+            choice if choice == 0 || len == 0 => CommandKind::Select {
+                inventory: rng.gen_range(0..selection.inventories.len()),
+            },
+            1 => CommandKind::Discard {
+                inventory,
+                item: rng.gen_range(0..len),
+            },
+            2 => CommandKind::MoveItem {
+                inventory,
+                from: rng.gen_range(0..len),
+                to: rng.gen_range(0..len),
+            },
+            _ => CommandKind::Build {
+                inventory,
+                item: 0,
+                // Right of the inventories.
+                position: Vec2::new(
+                    rng.gen_range(150f32..350f32),
+                    rng.gen_range(-250f32..250f32),
+                ),
+                rotation: Quat::from_rotation_z(FRAC_PI_2 * rng.gen_range(0..4) as f32),
+            },
+        };
+        queue.submit(*player, &tick, kind);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(inventory: usize, item: usize) -> CommandKind {
+        CommandKind::Build {
+            inventory,
+            item,
+            position: Vec2::ZERO,
+            rotation: Quat::IDENTITY,
+        }
+    }
+
+    #[test]
+    fn due_commands_by_tick_then_player() {
+        let mut queue = CommandQueue::default();
+        let select = |inventory| CommandKind::Select { inventory };
+        queue.submit(PlayerId(1), &Tick(2), select(0));
+        queue.submit(PlayerId(0), &Tick(3), select(1));
+        queue.submit(PlayerId(1), &Tick(1), select(2));
+        queue.submit(PlayerId(0), &Tick(2), select(3));
+        queue.submit(PlayerId(1), &Tick(2), select(4));
+        let due: Vec<(u64, PlayerId, CommandKind)> = queue
+            .take_due(2)
+            .into_iter()
+            .map(|c| (c.tick, c.player, c.kind))
+            .collect();
+        assert_eq!(
+            due,
+            vec![
+                (1, PlayerId(1), select(2)),
+                (2, PlayerId(0), select(3)),
+                (2, PlayerId(1), select(0)),
+                (2, PlayerId(1), select(4)),
+            ]
+        );
+        assert_eq!(queue.pending.len(), 1);
+        assert_eq!(queue.pending[0].tick, 3);
+        assert!(queue.take_due(2).is_empty());
+    }
+
+    #[test]
+    fn local_player_is_one_of_the_players() {
+        assert_eq!(checked_local_player(1, 2), PlayerId(1));
+        assert_eq!(checked_local_player(2, 2), PlayerId(0));
+    }
+
+    #[test]
+    fn same_item_is_built_once() {
+        let mut app = App::new();
+        app.init_resource::<Tick>();
+        app.init_resource::<CommandQueue>();
+        app.init_resource::<Wallet>();
+        app.init_resource::<Time<Virtual>>();
+        app.init_resource::<Time<Simulation>>();
+        app.add_event::<CraftRequest>();
+        app.add_event::<PickUpRequest>();
+        app.add_event::<HistoryRequest>();
+        app.add_systems(Update, execute_commands);
+        let items = [
+            app.world.spawn(enemies::ItemType::Gun).id(),
+            app.world.spawn(enemies::ItemType::Aura).id(),
+        ];
+        let inventory = app
+            .world
+            .spawn(Inventory::<enemies::ItemType> {
+                items: items.into(),
+                ..default()
+            })
+            .id();
+        for player in [PlayerId(0), PlayerId(1)] {
+            app.world.spawn((
+                player,
+                Selection {
+                    inventories: vec![inventory],
+                    selected_index: 0,
+                },
+            ));
+        }
+        let mut queue = app.world.resource_mut::<CommandQueue>();
+        queue.submit(PlayerId(1), &Tick(0), build(0, 0));
+        queue.submit(PlayerId(0), &Tick(0), build(0, 0));
+        queue.submit(PlayerId(0), &Tick(0), build(0, 1));
+        queue.submit(
+            PlayerId(0),
+            &Tick(0),
+            CommandKind::Craft {
+                inventory: 0,
+                from: 1,
+                onto: 0,
+            },
+        );
+        app.update();
+
+        assert!(app.world.resource::<Events<CraftRequest>>().is_empty());
+
+        let mut requests: Vec<Entity> = app
+            .world
+            .query::<&enemies::interaction::BuildRequest>()
+            .iter(&app.world)
+            .map(|r| r.item)
+            .collect();
+        requests.sort();
+        assert_eq!(requests, items.to_vec());
+    }
+}
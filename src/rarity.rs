@@ -0,0 +1,166 @@
+use crate::actions::{Action, ActionState, InteractionSet};
+use crate::inventory_generic::MarkerItemVisual;
+use crate::player_command::{CommandKind, CommandQueue, LocalPlayer, PlayerId, Tick};
+use crate::Selection;
+use bevy::prelude::*;
+use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+use bevy::utils::HashMap;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::VecDeque;
+
+/// Optional quality tier of an item, items without it are treated as below [`Rarity::Common`].
+#[derive(Component, Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rarity {
+    Common,
+    Uncommon,
+    Rare,
+    Epic,
+    Legendary,
+}
+
+impl Rarity {
+    pub const ALL: [Rarity; 5] = [
+        Rarity::Common,
+        Rarity::Uncommon,
+        Rarity::Rare,
+        Rarity::Epic,
+        Rarity::Legendary,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rarity::Common => "Common",
+            Rarity::Uncommon => "Uncommon",
+            Rarity::Rare => "Rare",
+            Rarity::Epic => "Epic",
+            Rarity::Legendary => "Legendary",
+        }
+    }
+
+    /// Relative chance to be rolled on refill.
+    pub fn loot_weight(&self) -> u32 {
+        match self {
+            Rarity::Common => 60,
+            Rarity::Uncommon => 25,
+            Rarity::Rare => 10,
+            Rarity::Epic => 4,
+            Rarity::Legendary => 1,
+        }
+    }
+
+    /// Color of the frame drawn behind the item, `None` for no frame.
+    ///
+    /// Higher tiers go above 1.0 so the HDR camera bloom makes them glow.
+    pub fn frame_color(&self) -> Option<Color> {
+        match self {
+            Rarity::Common => None,
+            Rarity::Uncommon => Some(Color::rgb(0.2, 0.8, 0.2)),
+            Rarity::Rare => Some(Color::rgb(0.2, 0.5, 1.5)),
+            Rarity::Epic => Some(Color::rgb(1.6, 0.3, 2.2)),
+            Rarity::Legendary => Some(Color::rgb(4.0, 2.4, 0.3)),
+        }
+    }
+
+    /// Color for text, in displayable range.
+    pub fn text_color(&self) -> Color {
+        match self {
+            Rarity::Common => Color::GRAY,
+            Rarity::Uncommon => Color::rgb(0.2, 0.8, 0.2),
+            Rarity::Rare => Color::rgb(0.3, 0.5, 1.0),
+            Rarity::Epic => Color::rgb(0.7, 0.3, 1.0),
+            Rarity::Legendary => Color::ORANGE,
+        }
+    }
+
+    pub fn roll(rng: &mut impl Rng) -> Rarity {
+        *Rarity::ALL
+            .choose_weighted(rng, Rarity::loot_weight)
+            .unwrap()
+    }
+}
+
+/// Frame is that much bigger than the item it surrounds.
+const FRAME_SCALE: f32 = 1.15f32;
+
+#[derive(Resource)]
+struct FrameMaterials {
+    by_rarity: HashMap<Rarity, Handle<ColorMaterial>>,
+}
+
+#[derive(Component)]
+pub struct MarkerRarityFrame;
+
+pub struct RarityPlugin;
+
+impl Plugin for RarityPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, create_frame_materials);
+        app.add_systems(PostUpdate, spawn_rarity_frame);
+        app.add_systems(Update, sort_selected.in_set(InteractionSet::World));
+    }
+}
+
+fn create_frame_materials(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    commands.insert_resource(FrameMaterials {
+        by_rarity: Rarity::ALL
+            .iter()
+            .filter_map(|r| Some((*r, materials.add(ColorMaterial::from(r.frame_color()?)))))
+            .collect(),
+    });
+}
+
+fn spawn_rarity_frame(
+    mut commands: Commands,
+    frame_materials: Res<FrameMaterials>,
+    q_items: Query<(Entity, &Rarity, &Mesh2dHandle), Added<MarkerItemVisual>>,
+) {
+    for (entity, rarity, mesh) in q_items.iter() {
+        let Some(material) = frame_materials.by_rarity.get(rarity) else {
+            continue;
+        };
+        let frame = commands
+            .spawn((
+                MaterialMesh2dBundle {
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                    // Behind the item, relative to its scale.
+                    transform: Transform::from_xyz(0f32, 0f32, -0.01f32)
+                        .with_scale(Vec3::splat(FRAME_SCALE)),
+                    ..default()
+                },
+                MarkerRarityFrame,
+            ))
+            .id();
+        commands.entity(entity).add_child(frame);
+    }
+}
+
+/// [`Action::Sort`] sorts the selected inventory by decreasing rarity.
+fn sort_selected(
+    actions: Res<ActionState>,
+    selection: Query<(&PlayerId, &Selection), With<LocalPlayer>>,
+    tick: Res<Tick>,
+    mut queue: ResMut<CommandQueue>,
+) {
+    if !actions.just_pressed(Action::Sort) {
+        return;
+    }
+    let Ok((player, selection)) = selection.get_single() else {
+        return;
+    };
+    queue.submit(
+        *player,
+        &tick,
+        CommandKind::Sort {
+            inventory: selection.selected_index,
+        },
+    );
+}
+
+pub fn sort_by_rarity(items: &mut VecDeque<Entity>, q_rarity: &Query<&Rarity>) {
+    // Stable, so items of the same rarity keep their order.
+    items
+        .make_contiguous()
+        .sort_by_key(|item| std::cmp::Reverse(q_rarity.get(*item).ok().copied()));
+}
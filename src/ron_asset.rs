@@ -0,0 +1,64 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+use std::marker::PhantomData;
+
+/// Loads an `A` asset written in RON, from files ending with one of `extensions`.
+pub struct RonAssetLoader<A> {
+    extensions: &'static [&'static str],
+    _asset: PhantomData<fn() -> A>,
+}
+
+impl<A> RonAssetLoader<A> {
+    pub fn new(extensions: &'static [&'static str]) -> Self {
+        Self {
+            extensions,
+            _asset: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RonAssetLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl std::fmt::Display for RonAssetLoaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RonAssetLoaderError::Io(e) => write!(f, "could not read file: {e}"),
+            RonAssetLoaderError::Ron(e) => write!(f, "could not parse file: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for RonAssetLoaderError {}
+
+impl<A: Asset + for<'de> Deserialize<'de>> AssetLoader for RonAssetLoader<A> {
+    type Asset = A;
+    type Settings = ();
+    type Error = RonAssetLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<A, RonAssetLoaderError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader
+                .read_to_end(&mut bytes)
+                .await
+                .map_err(RonAssetLoaderError::Io)?;
+            ron::de::from_bytes(&bytes).map_err(RonAssetLoaderError::Ron)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+}
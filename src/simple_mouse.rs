@@ -1,9 +1,21 @@
+use crate::tooltip::TooltipSet;
+use bevy::render::camera::NormalizedRenderTarget;
+use bevy::utils::HashMap;
 use bevy::{prelude::*, window::PrimaryWindow};
 
 /// We will store the world position of the mouse cursor here.
+/// It comes from the topmost camera under the cursor, and is kept when the cursor leaves.
 #[derive(Resource, Default)]
 pub struct MouseWorldPosition(pub Vec2);
 
+/// Cursor position seen by each camera rendering to the window under the cursor.
+#[derive(Resource, Default)]
+pub struct CameraCursors {
+    /// Highest order camera whose viewport contains the cursor, it drives [`MouseWorldPosition`].
+    pub hovered_camera: Option<Entity>,
+    pub world_positions: HashMap<Entity, Vec2>,
+}
+
 /// Used to help identify our main camera
 #[derive(Component)]
 pub struct MainCamera;
@@ -13,31 +25,119 @@ pub struct MousePlugin;
 impl Plugin for MousePlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<MouseWorldPosition>();
-        app.add_systems(Update, my_cursor_system);
+        app.init_resource::<CameraCursors>();
+        // Before hovering is checked, so a touch hovers what it presses the same frame.
+        app.add_systems(Update, my_cursor_system.before(TooltipSet::Collect));
     }
 }
 
-fn my_cursor_system(
+pub(crate) fn my_cursor_system(
     mut mycoords: ResMut<MouseWorldPosition>,
-    // query to get the window (so we can read the current cursor position)
-    q_window: Query<&Window, With<PrimaryWindow>>,
-    // query to get camera transform
-    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut cursors: ResMut<CameraCursors>,
+    q_window: Query<(Entity, &Window)>,
+    q_primary: Query<Entity, With<PrimaryWindow>>,
+    q_camera: Query<(Entity, &Camera, &GlobalTransform)>,
+    touches: Res<Touches>,
+) {
+    let primary = q_primary.get_single().ok();
+    // a touch wins over the cursor, lifted fingers count the frame they are released
+    // touches don't know their window, they are on the primary one
+    let touch_position = touches
+        .first_pressed_position()
+        .or_else(|| touches.iter_just_released().next().map(|t| t.position()));
+    let cursor = touch_position
+        .zip(primary)
+        .map(|(position, window)| (window, position))
+        .or_else(|| {
+            q_window
+                .iter()
+                .find_map(|(entity, window)| window.cursor_position().map(|c| (entity, c)))
+        });
+    // no window (headless) or the cursor is outside of every window
+    let Some((window, cursor)) = cursor else {
+        cursors.hovered_camera = None;
+        cursors.world_positions.clear();
+        return;
+    };
+    map_cursor(
+        window,
+        cursor,
+        primary,
+        &q_camera,
+        &mut cursors,
+        &mut mycoords,
+    );
+}
+
+/// Converts `cursor`, in logical pixels of `window`, for every camera rendering to that window.
+pub fn map_cursor(
+    window: Entity,
+    cursor: Vec2,
+    primary: Option<Entity>,
+    q_camera: &Query<(Entity, &Camera, &GlobalTransform)>,
+    cursors: &mut CameraCursors,
+    mouse_world_position: &mut MouseWorldPosition,
 ) {
-    // get the camera info and transform
-    // assuming there is exactly one main camera entity, so Query::single() is OK
-    let (camera, camera_transform) = q_camera.single();
-
-    // There is only one primary window, so we can similarly get it from the query:
-    let window = q_window.single();
-
-    // check if the cursor is inside the window and get its position
-    // then, ask bevy to convert into world coordinates, and truncate to discard Z
-    if let Some(world_position) = window
-        .cursor_position()
-        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
-        .map(|ray| ray.origin.truncate())
-    {
-        mycoords.0 = world_position;
+    cursors.hovered_camera = None;
+    cursors.world_positions.clear();
+    let mut hovered_order = None;
+    for (entity, camera, camera_transform) in q_camera.iter() {
+        if !camera.is_active {
+            continue;
+        }
+        let Some(NormalizedRenderTarget::Window(target)) = camera.target.normalize(primary) else {
+            continue;
+        };
+        if target.entity() != window {
+            continue;
+        }
+        let Some(viewport) = camera.logical_viewport_rect() else {
+            continue;
+        };
+        // viewport coordinates start at its top left corner
+        let Some(world_position) =
+            camera.viewport_to_world_2d(camera_transform, cursor - viewport.min)
+        else {
+            continue;
+        };
+        cursors.world_positions.insert(entity, world_position);
+        if viewport.contains(cursor) && hovered_order.is_none_or(|order| camera.order > order) {
+            hovered_order = Some(camera.order);
+            cursors.hovered_camera = Some(entity);
+            mouse_world_position.0 = world_position;
+        }
+    }
+}
+
+/// Whether `cursor` (in world coordinates) is over a visual of `size` before scaling (quad, or
+/// circle of diameter 1) placed with `transform`, which handles its scale and rotation.
+pub fn cursor_over(transform: &GlobalTransform, cursor: Vec2, size: Vec2) -> bool {
+    let local = transform
+        .affine()
+        .inverse()
+        .transform_point3(cursor.extend(transform.translation().z));
+    local.x.abs() <= size.x / 2f32 && local.y.abs() <= size.y / 2f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn cursor_over_uses_size_scale_and_rotation() {
+        let size = Vec2::new(1f32, 0.5f32);
+        let transform = GlobalTransform::from(
+            Transform::from_xyz(100f32, 0f32, 0f32).with_scale(Vec3::splat(64f32)),
+        );
+        assert!(cursor_over(&transform, Vec2::new(131f32, 15f32), size));
+        assert!(!cursor_over(&transform, Vec2::new(100f32, 17f32), size));
+        let rotated = GlobalTransform::from(
+            Transform::from_xyz(100f32, 0f32, 0f32)
+                .with_rotation(Quat::from_rotation_z(FRAC_PI_2))
+                .with_scale(Vec3::splat(64f32)),
+        );
+        assert!(cursor_over(&rotated, Vec2::new(100f32, 31f32), size));
+        assert!(!cursor_over(&rotated, Vec2::new(117f32, 0f32), size));
     }
 }
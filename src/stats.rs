@@ -0,0 +1,272 @@
+use crate::rarity::Rarity;
+use bevy::prelude::*;
+use rand::Rng;
+use std::marker::PhantomData;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum StatKind {
+    Damage,
+    Range,
+    /// Shots per second.
+    FireRate,
+    Radius,
+}
+
+impl StatKind {
+    pub const ALL: [StatKind; 4] = [
+        StatKind::Damage,
+        StatKind::Range,
+        StatKind::FireRate,
+        StatKind::Radius,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StatKind::Damage => "Damage",
+            StatKind::Range => "Range",
+            StatKind::FireRate => "Fire rate",
+            StatKind::Radius => "Radius",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub damage: f32,
+    pub range: f32,
+    pub fire_rate: f32,
+    pub radius: f32,
+}
+
+impl Stats {
+    pub fn get(&self, kind: StatKind) -> f32 {
+        match kind {
+            StatKind::Damage => self.damage,
+            StatKind::Range => self.range,
+            StatKind::FireRate => self.fire_rate,
+            StatKind::Radius => self.radius,
+        }
+    }
+
+    pub fn get_mut(&mut self, kind: StatKind) -> &mut f32 {
+        match kind {
+            StatKind::Damage => &mut self.damage,
+            StatKind::Range => &mut self.range,
+            StatKind::FireRate => &mut self.fire_rate,
+            StatKind::Radius => &mut self.radius,
+        }
+    }
+
+    /// Flat modifiers are added first, then percentages are summed and applied once.
+    pub fn with_modifiers<'a>(&self, modifiers: impl IntoIterator<Item = &'a Modifier>) -> Stats {
+        let mut flat = Stats::default();
+        let mut percent = Stats::default();
+        for modifier in modifiers {
+            match modifier.value {
+                ModifierValue::Flat(v) => *flat.get_mut(modifier.stat) += v,
+                ModifierValue::Percent(v) => *percent.get_mut(modifier.stat) += v,
+            }
+        }
+        let mut result = *self;
+        for kind in StatKind::ALL {
+            *result.get_mut(kind) =
+                (self.get(kind) + flat.get(kind)) * (1f32 + percent.get(kind) / 100f32);
+        }
+        result
+    }
+
+    /// (label, value) pairs of non zero stats, for tooltips.
+    pub fn describe(&self) -> Vec<(String, String)> {
+        StatKind::ALL
+            .iter()
+            .filter(|kind| self.get(**kind) != 0f32)
+            .map(|kind| (kind.name().to_string(), format!("{:.1}", self.get(*kind))))
+            .collect()
+    }
+}
+
+/// Implemented by item types which have gameplay stats.
+pub trait BaseStats {
+    fn base_stats(&self) -> Stats;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModifierValue {
+    Flat(f32),
+    Percent(f32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Modifier {
+    pub stat: StatKind,
+    pub value: ModifierValue,
+}
+
+impl Modifier {
+    pub fn describe(&self) -> String {
+        match self.value {
+            ModifierValue::Flat(v) => format!("{:+.1} {}", v, self.stat.name()),
+            ModifierValue::Percent(v) => format!("{:+.0}% {}", v, self.stat.name()),
+        }
+    }
+}
+
+/// Affixes rolled when the item is spawned.
+#[derive(Component, Clone, Debug, Default, PartialEq)]
+pub struct Modifiers(pub Vec<Modifier>);
+
+impl Modifiers {
+    /// Rolls one affix per rarity tier above common, only on stats the item actually uses.
+    pub fn roll(base: &Stats, rarity: Option<Rarity>, rng: &mut impl Rng) -> Modifiers {
+        let count = rarity.map_or(0, |r| r as usize);
+        let candidates: Vec<StatKind> = StatKind::ALL
+            .into_iter()
+            .filter(|kind| base.get(*kind) != 0f32)
+            .collect();
+        if candidates.is_empty() {
+            return Modifiers::default();
+        }
+        Modifiers(
+            (0..count)
+                .map(|_| {
+                    let stat = candidates[rng.gen_range(0..candidates.len())];
+                    let value = if rng.gen_bool(0.5) {
+                        ModifierValue::Percent(rng.gen_range(5..=25) as f32)
+                    } else {
+                        // Flat bonus worth 5 to 20% of the base value.
+                        let ratio = rng.gen_range(5..=20) as f32 / 100f32;
+                        ModifierValue::Flat((base.get(stat) * ratio * 10f32).round() / 10f32)
+                    };
+                    Modifier { stat, value }
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Each level above the first adds this percentage to every stat.
+const LEVEL_BONUS_PERCENT: f32 = 25f32;
+
+/// Upgrade level, starting at 1.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Level(pub u32);
+
+impl Default for Level {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+impl Level {
+    pub fn modifiers(&self) -> Vec<Modifier> {
+        let bonus = LEVEL_BONUS_PERCENT * self.0.saturating_sub(1) as f32;
+        if bonus == 0f32 {
+            return Vec::new();
+        }
+        StatKind::ALL
+            .iter()
+            .map(|stat| Modifier {
+                stat: *stat,
+                value: ModifierValue::Percent(bonus),
+            })
+            .collect()
+    }
+}
+
+/// Base stats of the item type with its [`Modifiers`] and [`Level`] applied, kept up to date by
+/// [`StatsPlugin`].
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct FinalStats(pub Stats);
+
+pub struct StatsPlugin<IT: Component + BaseStats> {
+    _item_type: PhantomData<IT>,
+}
+
+impl<IT: Component + BaseStats> Default for StatsPlugin<IT> {
+    fn default() -> Self {
+        Self {
+            _item_type: Default::default(),
+        }
+    }
+}
+
+impl<IT: Component + BaseStats> Plugin for StatsPlugin<IT> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PreUpdate, update_final_stats::<IT>);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_final_stats<IT: Component + BaseStats>(
+    mut commands: Commands,
+    q_items: Query<
+        (Entity, &IT, Option<&Modifiers>, Option<&Level>),
+        Or<(Added<IT>, Changed<Modifiers>, Changed<Level>)>,
+    >,
+) {
+    for (entity, item, modifiers, level) in q_items.iter() {
+        let modifiers = modifiers.map(|m| m.0.as_slice()).unwrap_or_default();
+        let level_modifiers = level.map(Level::modifiers).unwrap_or_default();
+        commands.entity(entity).insert(FinalStats(
+            item.base_stats()
+                .with_modifiers(modifiers.iter().chain(level_modifiers.iter())),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    fn modifier(stat: StatKind, value: ModifierValue) -> Modifier {
+        Modifier { stat, value }
+    }
+
+    #[test]
+    fn flat_then_summed_percent() {
+        let base = Stats {
+            damage: 10f32,
+            range: 100f32,
+            ..default()
+        };
+        let stats = base.with_modifiers(&[
+            modifier(StatKind::Damage, ModifierValue::Percent(50f32)),
+            modifier(StatKind::Damage, ModifierValue::Flat(10f32)),
+            modifier(StatKind::Damage, ModifierValue::Percent(50f32)),
+        ]);
+        assert_eq!(stats.damage, 40f32);
+        assert_eq!(stats.range, 100f32);
+    }
+
+    #[test]
+    fn level_adds_to_every_stat() {
+        let base = Stats {
+            damage: 10f32,
+            fire_rate: 2f32,
+            ..default()
+        };
+        assert_eq!(base.with_modifiers(&Level(1).modifiers()), base);
+        let stats = base.with_modifiers(&Level(3).modifiers());
+        assert_eq!(stats.damage, 15f32);
+        assert_eq!(stats.fire_rate, 3f32);
+        assert_eq!(stats.radius, 0f32);
+    }
+
+    #[test]
+    fn one_affix_per_tier_on_used_stats() {
+        let base = Stats {
+            radius: 120f32,
+            ..default()
+        };
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        assert!(Modifiers::roll(&base, None, &mut rng).0.is_empty());
+        assert!(Modifiers::roll(&base, Some(Rarity::Common), &mut rng)
+            .0
+            .is_empty());
+        let modifiers = Modifiers::roll(&base, Some(Rarity::Legendary), &mut rng);
+        assert_eq!(modifiers.0.len(), 4);
+        assert!(modifiers.0.iter().all(|m| m.stat == StatKind::Radius));
+    }
+}
@@ -0,0 +1,274 @@
+use crate::inventory_generic::MarkerItemVisual;
+use crate::rarity::Rarity;
+use crate::simple_mouse::{cursor_over, CameraCursors, MouseWorldPosition};
+use crate::stats::{FinalStats, Level, Modifiers};
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use bevy::window::PrimaryWindow;
+use std::marker::PhantomData;
+
+/// What a tooltip shows about an item.
+#[derive(Clone, Default)]
+pub struct ItemInfo {
+    pub name: String,
+    pub description: String,
+    /// (label, value) pairs, displayed one per line.
+    pub stats: Vec<(String, String)>,
+    /// Overridden by the item [`Rarity`] component when it has one.
+    pub rarity: Option<Rarity>,
+}
+
+/// Implemented by item types to describe themselves in tooltips.
+pub trait ItemMetadata {
+    fn item_info(&self) -> ItemInfo;
+
+    /// Size of the visual before scaling, the area hovering it.
+    fn visual_size(&self) -> Vec2 {
+        Vec2::ONE
+    }
+}
+
+#[derive(Resource)]
+pub struct TooltipSettings {
+    /// How long the cursor must stay over an item before its tooltip shows up.
+    pub delay: Duration,
+    /// Distance between the cursor and the tooltip, in logical pixels.
+    pub offset: Vec2,
+}
+
+impl Default for TooltipSettings {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_millis(400),
+            offset: Vec2::new(16f32, 16f32),
+        }
+    }
+}
+
+/// Item visual currently under the cursor, if any.
+///
+/// Found from [`MouseWorldPosition`], so touches and the gamepad cursor hover items too.
+#[derive(Resource, Default)]
+pub struct HoveredItem {
+    pub entity: Option<Entity>,
+    hovered_for: Duration,
+    /// Topmost (highest z) item found under the cursor this frame.
+    candidate: Option<(Entity, f32, ItemInfo)>,
+}
+
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub enum TooltipSet {
+    Collect,
+    Display,
+}
+
+#[derive(Component)]
+struct TooltipPanel;
+
+#[derive(Component)]
+struct TooltipText;
+
+/// Shared tooltip logic, item types register themselves with [`ItemTooltipPlugin`].
+pub struct TooltipPlugin;
+
+impl Plugin for TooltipPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TooltipSettings>();
+        app.init_resource::<HoveredItem>();
+        app.configure_sets(Update, (TooltipSet::Collect, TooltipSet::Display).chain());
+        app.add_systems(Startup, spawn_tooltip);
+        app.add_systems(Update, update_tooltip.in_set(TooltipSet::Display));
+    }
+}
+
+pub struct ItemTooltipPlugin<IT: Component + ItemMetadata> {
+    _item_type: PhantomData<IT>,
+}
+
+impl<IT: Component + ItemMetadata> Default for ItemTooltipPlugin<IT> {
+    fn default() -> Self {
+        Self {
+            _item_type: Default::default(),
+        }
+    }
+}
+
+impl<IT: Component + ItemMetadata> Plugin for ItemTooltipPlugin<IT> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            collect_hovered_item::<IT>
+                .in_set(TooltipSet::Collect)
+                // Order between item types does not matter, the topmost candidate wins.
+                .ambiguous_with(TooltipSet::Collect),
+        );
+    }
+}
+
+fn spawn_tooltip(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(8f32)),
+                    ..default()
+                },
+                background_color: Color::rgba(0.05, 0.05, 0.08, 0.9).into(),
+                visibility: Visibility::Hidden,
+                z_index: ZIndex::Global(i32::MAX),
+                ..default()
+            },
+            TooltipPanel,
+        ))
+        .with_children(|parent| {
+            parent.spawn((TextBundle::default(), TooltipText));
+        });
+}
+
+#[allow(clippy::type_complexity)]
+fn collect_hovered_item<IT: Component + ItemMetadata>(
+    mut hovered: ResMut<HoveredItem>,
+    mouse_position_world: Res<MouseWorldPosition>,
+    cursors: Res<CameraCursors>,
+    q_items: Query<
+        (
+            Entity,
+            &IT,
+            &GlobalTransform,
+            Option<&Rarity>,
+            Option<&FinalStats>,
+            Option<&Modifiers>,
+            Option<&Level>,
+        ),
+        With<MarkerItemVisual>,
+    >,
+) {
+    // The last position is kept when the pointer leaves the window, it hovers nothing.
+    if cursors.hovered_camera.is_none() {
+        return;
+    }
+    for (entity, item, transform, rarity, stats, modifiers, level) in q_items.iter() {
+        if !cursor_over(transform, mouse_position_world.0, item.visual_size()) {
+            continue;
+        }
+        let z = transform.translation().z;
+        if matches!(hovered.candidate, Some((_, best_z, _)) if best_z >= z) {
+            continue;
+        }
+        let mut info = item.item_info();
+        if let Some(rarity) = rarity {
+            info.rarity = Some(*rarity);
+        }
+        if let Some(level) = level {
+            info.stats.push(("Level".to_string(), level.0.to_string()));
+        }
+        if let Some(stats) = stats {
+            info.stats.extend(stats.0.describe());
+        }
+        for modifier in modifiers.iter().flat_map(|m| m.0.iter()) {
+            info.stats.push(("Affix".to_string(), modifier.describe()));
+        }
+        hovered.candidate = Some((entity, z, info));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_tooltip(
+    time: Res<Time>,
+    settings: Res<TooltipSettings>,
+    mut hovered: ResMut<HoveredItem>,
+    mouse_position_world: Res<MouseWorldPosition>,
+    cursors: Res<CameraCursors>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    mut q_panel: Query<(&mut Style, &mut Visibility, &Node), With<TooltipPanel>>,
+    mut q_text: Query<&mut Text, With<TooltipText>>,
+) {
+    let candidate = hovered.candidate.take();
+    let Ok((mut style, mut visibility, node)) = q_panel.get_single_mut() else {
+        return;
+    };
+    let Some((entity, _, info)) = candidate else {
+        hovered.entity = None;
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    if hovered.entity != Some(entity) {
+        hovered.entity = Some(entity);
+        hovered.hovered_for = Duration::ZERO;
+    } else {
+        hovered.hovered_for += time.delta();
+    }
+    if hovered.hovered_for < settings.delay {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    // Back to window pixels through the camera the pointer was mapped with, whether it is the
+    // mouse, a touch or the gamepad cursor.
+    let cursor = cursors
+        .hovered_camera
+        .and_then(|camera| q_camera.get(camera).ok())
+        .and_then(|(camera, transform)| {
+            let viewport = camera.logical_viewport_rect()?;
+            let position =
+                camera.world_to_viewport(transform, mouse_position_world.0.extend(0f32))?;
+            Some(position + viewport.min)
+        });
+    let (Some(cursor), Ok(window)) = (cursor, q_window.get_single()) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    let window_size = Vec2::new(window.width(), window.height());
+
+    if let Ok(mut text) = q_text.get_single_mut() {
+        *text = tooltip_text(&info);
+    }
+    *visibility = Visibility::Visible;
+
+    // Node size is from last layout, good enough as the content rarely changes while hovering.
+    let size = node.size();
+    let mut position = cursor + settings.offset;
+    if position.x + size.x > window_size.x {
+        position.x = cursor.x - settings.offset.x - size.x;
+    }
+    if position.y + size.y > window_size.y {
+        position.y = cursor.y - settings.offset.y - size.y;
+    }
+    position = position.clamp(Vec2::ZERO, (window_size - size).max(Vec2::ZERO));
+    style.left = Val::Px(position.x);
+    style.top = Val::Px(position.y);
+}
+
+fn tooltip_text(info: &ItemInfo) -> Text {
+    let style = |font_size: f32, color: Color| TextStyle {
+        font_size,
+        color,
+        ..default()
+    };
+    let name_color = info.rarity.map_or(Color::WHITE, |r| r.text_color());
+    let mut sections = vec![TextSection::new(
+        info.name.clone(),
+        style(20f32, name_color),
+    )];
+    if let Some(rarity) = &info.rarity {
+        sections.push(TextSection::new(
+            format!("\n{}", rarity.name()),
+            style(14f32, rarity.text_color()),
+        ));
+    }
+    if !info.description.is_empty() {
+        sections.push(TextSection::new(
+            format!("\n{}", info.description),
+            style(14f32, Color::GRAY),
+        ));
+    }
+    for (label, value) in info.stats.iter() {
+        sections.push(TextSection::new(
+            format!("\n{label}: {value}"),
+            style(14f32, Color::WHITE),
+        ));
+    }
+    Text::from_sections(sections)
+}